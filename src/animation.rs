@@ -0,0 +1,611 @@
+use crate::enemy::Patrol;
+use crate::player::{Climber, GroundDetection, Player};
+use crate::ron_loader::RonLoader;
+use crate::stats::CharacterKind;
+use crate::GameSet;
+use bevy::math::Vec3A;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// Sprite animations, and smoothing bodies out between fixed steps
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimationLibrary>()
+            .register_asset_loader(RonLoader::<AnimationLibrary>::new(&["anim.ron"]))
+            .add_event::<AnimationEvent>()
+            .add_systems(
+                Update,
+                (
+                    add_animators,
+                    (add_transform_interpolation, interpolate_transforms)
+                        .chain()
+                        .in_set(GameSet::Movement),
+                    (
+                        (update_player_animation_params, face_patrol_direction),
+                        run_animation_graphs,
+                        advance_animators,
+                    )
+                        .chain()
+                        .in_set(GameSet::Animation),
+                ),
+            )
+            // - Interpolation between fixed steps
+            .add_systems(First, restore_physics_transforms)
+            .add_systems(FixedLast, record_fixed_transforms)
+            .add_systems(
+                PostUpdate,
+                apply_interpolated_transforms.after(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Follow at most this many transitions a frame, so a loop in a graph can't hang the game
+const MAX_TRANSITIONS_PER_FRAME: usize = 4;
+
+/// Named clips of one character and the graph choosing between them, from an `.anim.ron` file
+#[derive(Asset, TypePath, Clone, Debug, Default, Deserialize)]
+pub struct AnimationLibrary {
+    /// The starting state, or the clip that always plays when there are no states
+    pub default: String,
+    pub clips: HashMap<String, Clip>,
+    #[serde(default)]
+    pub states: HashMap<String, AnimationNode>,
+    /// Transitions checked from every state, before the state's own
+    #[serde(default)]
+    pub any_state: Vec<Transition>,
+}
+
+impl AnimationLibrary {
+    /// The sprite sheet index shown when the library starts playing
+    pub fn first_frame(&self) -> Option<usize> {
+        let clip = self
+            .states
+            .get(&self.default)
+            .map_or(&self.default, |node| &node.clip);
+        self.clips.get(clip)?.frames.first().copied()
+    }
+}
+
+/// A state of the animation graph
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AnimationNode {
+    pub clip: String,
+    /// Checked in order, the first one whose conditions all hold is taken
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    /// Holds the current frame while all of these hold, like on a ladder while not moving
+    #[serde(default)]
+    pub pause_when: Vec<Condition>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Transition {
+    pub to: String,
+    #[serde(default)]
+    pub when: Vec<Condition>,
+}
+
+/// Checks against the entity's `AnimationParams`, unset parameters read as 0
+#[derive(Clone, Debug, Deserialize)]
+pub enum Condition {
+    Flag(String),
+    NotFlag(String),
+    Above(String, f32),
+    Below(String, f32),
+    /// Set by `AnimationParams::trigger` until the graph next runs, and used up by the transition taking it
+    Trigger(String),
+    /// The clip is `Once` and on its last frame
+    Finished,
+}
+
+impl Condition {
+    fn holds(&self, params: &AnimationParams, animator: &Animator) -> bool {
+        match self {
+            Condition::Flag(name) => params.get(name) != 0.,
+            Condition::NotFlag(name) => params.get(name) == 0.,
+            Condition::Above(name, value) => params.get(name) > *value,
+            Condition::Below(name, value) => params.get(name) < *value,
+            Condition::Trigger(name) => params.triggers.contains(name),
+            Condition::Finished => animator.finished,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Clip {
+    /// Indices into the sprite sheet
+    pub frames: Vec<usize>,
+    pub fps: f32,
+    #[serde(default)]
+    pub mode: LoopMode,
+    /// `AnimationEvent`s sent as a frame comes up, keyed by its position in `frames`
+    #[serde(default)]
+    pub events: HashMap<usize, Vec<String>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+pub enum LoopMode {
+    #[default]
+    Repeat,
+    /// Stops on the last frame
+    Once,
+}
+
+/// Plays clips from a library onto the entity's `TextureAtlas`
+#[derive(Clone, Debug, Default, Component)]
+pub struct Animator {
+    pub library: Handle<AnimationLibrary>,
+    /// Current state of the library's graph, if it has one
+    state: String,
+    clip: String,
+    /// Position in the clip's frames
+    frame: usize,
+    elapsed: f32,
+    /// Whether the current frame's events were sent
+    entered: bool,
+    /// Set once a `Once` clip reaches its last frame
+    pub finished: bool,
+    /// Holds the current frame, like on a ladder while not moving
+    pub paused: bool,
+}
+
+impl Animator {
+    pub fn new(library: Handle<AnimationLibrary>) -> Self {
+        Animator {
+            library,
+            ..Default::default()
+        }
+    }
+
+    /// Switches clip, starting it over unless it's already playing
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            self.restart(clip);
+        }
+    }
+
+    pub fn restart(&mut self, clip: &str) {
+        self.clip = clip.to_string();
+        self.frame = 0;
+        self.elapsed = 0.;
+        self.entered = false;
+        self.finished = false;
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+}
+
+/// What gameplay tells the animation graph, by name
+#[derive(Clone, Debug, Default, Component)]
+pub struct AnimationParams {
+    values: HashMap<String, f32>,
+    triggers: HashSet<String>,
+}
+
+impl AnimationParams {
+    pub fn set(&mut self, name: &str, value: f32) {
+        if let Some(current) = self.values.get_mut(name) {
+            *current = value;
+        } else {
+            self.values.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn set_flag(&mut self, name: &str, value: bool) {
+        self.set(name, if value { 1. } else { 0. });
+    }
+
+    /// Fires transitions waiting on `Trigger(name)`
+    pub fn trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+
+    fn get(&self, name: &str) -> f32 {
+        self.values.get(name).copied().unwrap_or_default()
+    }
+}
+
+/// A named frame of a clip came up, for sounds and hits in sync with the animation
+#[derive(Event, Clone, Debug)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// Characters find their clips in `animations/<identifier>.anim.ron`
+pub fn library_path(kind: &CharacterKind) -> String {
+    format!("animations/{}.anim.ron", kind.0.to_lowercase())
+}
+
+/// Gives each new character an animator playing its library
+pub fn add_animators(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    characters: Query<(Entity, &CharacterKind), Added<CharacterKind>>,
+) {
+    for (entity, kind) in &characters {
+        commands.entity(entity).insert((
+            Animator::new(asset_server.load(library_path(kind))),
+            AnimationParams::default(),
+        ));
+    }
+}
+
+/// Feeds the player's movement to their animation graph
+pub fn update_player_animation_params(
+    mut query: Query<
+        (
+            &mut Sprite,
+            &Velocity,
+            &Climber,
+            &GroundDetection,
+            &mut AnimationParams,
+        ),
+        With<Player>,
+    >,
+) {
+    for (mut sprite, velocity, climber, ground, mut params) in &mut query {
+        if velocity.linvel.x < -15.0 {
+            sprite.flip_x = true;
+        } else if velocity.linvel.x > 15.0 {
+            sprite.flip_x = false;
+        }
+        params.set_flag("on_ground", ground.on_ground);
+        params.set_flag("climbing", climber.climbing);
+        params.set("speed_x", velocity.linvel.x.abs());
+        params.set("speed_y", velocity.linvel.y.abs());
+        params.set("velocity_y", velocity.linvel.y);
+    }
+}
+
+pub fn face_patrol_direction(mut query: Query<(&mut Sprite, &Velocity), With<Patrol>>) {
+    for (mut sprite, velocity) in &mut query {
+        sprite.flip_x = velocity.linvel.x <= 0.0;
+    }
+}
+
+/// Moves each animator through its library's graph, and plays the clip of the state it ends up in
+pub fn run_animation_graphs(
+    libraries: Res<Assets<AnimationLibrary>>,
+    mut query: Query<(&mut Animator, &mut AnimationParams)>,
+) {
+    for (mut animator, mut params) in &mut query {
+        match libraries.get(&animator.library) {
+            Some(library) => step_graph(library, &mut animator, &mut params),
+            None => params.triggers.clear(),
+        }
+    }
+}
+
+/// Follows the transitions whose conditions hold, taking up the triggers they wait on
+fn step_graph(library: &AnimationLibrary, animator: &mut Animator, params: &mut AnimationParams) {
+    if library.states.is_empty() {
+        params.triggers.clear();
+        return;
+    }
+    if !library.states.contains_key(&animator.state) {
+        if !library.states.contains_key(&library.default) {
+            warn_once!("Animation graph has no default state {}", library.default);
+            return;
+        }
+        animator.state = library.default.clone();
+        animator.finished = false;
+    }
+
+    for _ in 0..MAX_TRANSITIONS_PER_FRAME {
+        let Some(transition) = library
+            .any_state
+            .iter()
+            .chain(&library.states[&animator.state].transitions)
+            .filter(|transition| transition.to != animator.state)
+            .find(|transition| {
+                transition
+                    .when
+                    .iter()
+                    .all(|condition| condition.holds(params, animator))
+            })
+        else {
+            break;
+        };
+        let Some(next) = library.states.get(&transition.to) else {
+            warn_once!("Animation graph has no state {}", transition.to);
+            break;
+        };
+        for condition in &transition.when {
+            if let Condition::Trigger(name) = condition {
+                params.triggers.remove(name);
+            }
+        }
+        animator.state = transition.to.clone();
+        animator.restart(&next.clip);
+    }
+
+    let node = &library.states[&animator.state];
+    animator.play(&node.clip);
+    animator.paused = !node.pause_when.is_empty()
+        && node
+            .pause_when
+            .iter()
+            .all(|condition| condition.holds(params, animator));
+    // Triggers nothing was waiting for don't carry over
+    params.triggers.clear();
+}
+
+/// Steps every animator through its clip and shows the frame
+pub fn advance_animators(
+    time: Res<Time>,
+    libraries: Res<Assets<AnimationLibrary>>,
+    mut query: Query<(Entity, &mut Animator, &mut TextureAtlas)>,
+    mut events: EventWriter<AnimationEvent>,
+) {
+    for (entity, mut animator, mut atlas) in &mut query {
+        let Some(library) = libraries.get(&animator.library) else {
+            continue;
+        };
+        if !library.clips.contains_key(&animator.clip) {
+            let default = library.default.clone();
+            animator.play(&default);
+        }
+        let Some(clip) = library.clips.get(&animator.clip) else {
+            warn_once!("Animation library has no default clip {}", library.default);
+            continue;
+        };
+        if clip.frames.is_empty() {
+            continue;
+        }
+        // The library may have been hot reloaded with fewer frames
+        animator.frame = animator.frame.min(clip.frames.len() - 1);
+
+        if !animator.paused && !animator.finished {
+            animator.elapsed += time.delta_seconds();
+        }
+        let frame_time = 1. / clip.fps.max(f32::EPSILON);
+        loop {
+            if !animator.entered {
+                animator.entered = true;
+                for name in clip.events.get(&animator.frame).into_iter().flatten() {
+                    events.send(AnimationEvent {
+                        entity,
+                        name: name.clone(),
+                    });
+                }
+            }
+            if animator.finished || animator.elapsed < frame_time {
+                break;
+            }
+            animator.elapsed -= frame_time;
+            if animator.frame + 1 < clip.frames.len() {
+                animator.frame += 1;
+            } else if clip.mode == LoopMode::Repeat {
+                animator.frame = 0;
+            } else {
+                animator.finished = true;
+                continue;
+            }
+            animator.entered = false;
+        }
+        atlas.index = clip.frames[animator.frame];
+    }
+}
+
+/// Smooths out bodies moved by the fixed timestep, so they don't judder at higher frame rates
+#[derive(Clone, Default, Component)]
+pub struct TransformInterpolation {
+    /// Translations after the last two fixed steps
+    pub previous: Vec3,
+    pub current: Vec3,
+    /// Translation drawn this frame
+    pub rendered: Vec3,
+    /// What the `GlobalTransform`s of the body and everything under it were
+    /// before being moved to the drawn translation
+    pub physics_global_transforms: Vec<(Entity, GlobalTransform)>,
+}
+
+pub fn add_transform_interpolation(
+    mut commands: Commands,
+    bodies: Query<(Entity, &RigidBody, &Transform), Added<RigidBody>>,
+) {
+    for (entity, rigid_body, transform) in &bodies {
+        if *rigid_body != RigidBody::Fixed {
+            commands.entity(entity).insert(TransformInterpolation {
+                previous: transform.translation,
+                current: transform.translation,
+                rendered: transform.translation,
+                physics_global_transforms: Vec::new(),
+            });
+        }
+    }
+}
+
+pub fn record_fixed_transforms(mut query: Query<(&Transform, &mut TransformInterpolation)>) {
+    for (transform, mut interpolation) in &mut query {
+        interpolation.previous = interpolation.current;
+        interpolation.current = transform.translation;
+    }
+}
+
+/// Places bodies part way between the last two fixed steps, by how far into the next one we are
+pub fn interpolate_transforms(
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<&mut TransformInterpolation>,
+) {
+    let t = fixed_time.overstep_fraction();
+    for mut interpolation in &mut query {
+        interpolation.rendered = interpolation.previous.lerp(interpolation.current, t);
+    }
+}
+
+/// Only the `GlobalTransform`s get moved, so the physics never sees the drawn position.
+/// Children move along with the body, or attached sprites would judder against it.
+pub fn apply_interpolated_transforms(
+    mut bodies: Query<(Entity, &Transform, &mut TransformInterpolation)>,
+    children: Query<&Children>,
+    mut global_transforms: Query<&mut GlobalTransform>,
+) {
+    for (entity, transform, mut interpolation) in &mut bodies {
+        let offset = Vec3A::from(interpolation.rendered - transform.translation);
+        interpolation.physics_global_transforms.clear();
+        for moved in std::iter::once(entity).chain(children.iter_descendants(entity)) {
+            let Ok(mut global_transform) = global_transforms.get_mut(moved) else {
+                continue;
+            };
+            interpolation
+                .physics_global_transforms
+                .push((moved, *global_transform));
+            let mut affine = global_transform.affine();
+            affine.translation += offset;
+            *global_transform = affine.into();
+        }
+    }
+}
+
+/// Puts back what the physics last wrote before the next fixed step reads it.
+/// Children only get propagated again when something moved, so they need it too.
+pub fn restore_physics_transforms(
+    mut bodies: Query<&mut TransformInterpolation>,
+    mut global_transforms: Query<&mut GlobalTransform>,
+) {
+    for mut interpolation in &mut bodies {
+        for (entity, physics_global_transform) in interpolation.physics_global_transforms.drain(..)
+        {
+            if let Ok(mut global_transform) = global_transforms.get_mut(entity) {
+                *global_transform = physics_global_transform;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = r#"(
+        default: "idle",
+        clips: {
+            "idle": (frames: [0, 1], fps: 2.0),
+            "run": (frames: [2, 3], fps: 12.0),
+            "slash": (frames: [4, 5, 6], fps: 12.0, mode: Once),
+        },
+        any_state: [(to: "slash", when: [Trigger("slash")])],
+        states: {
+            "idle": (
+                clip: "idle",
+                transitions: [(to: "run", when: [Above("speed_x", 15.0)])],
+            ),
+            "run": (
+                clip: "run",
+                transitions: [(to: "idle", when: [Below("speed_x", 15.0)])],
+            ),
+            "slash": (
+                clip: "slash",
+                transitions: [(to: "idle", when: [Finished])],
+            ),
+        },
+    )"#;
+
+    fn library() -> AnimationLibrary {
+        ron::from_str(LIBRARY).expect("test library should parse")
+    }
+
+    /// An animator that has stepped once, so it sits in the default state
+    fn idle_animator(library: &AnimationLibrary) -> (Animator, AnimationParams) {
+        let mut animator = Animator::default();
+        let mut params = AnimationParams::default();
+        step_graph(library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "idle");
+        assert_eq!(animator.clip(), "idle");
+        (animator, params)
+    }
+
+    #[test]
+    fn trigger_takes_its_transition_once() {
+        let library = library();
+        let (mut animator, mut params) = idle_animator(&library);
+
+        params.trigger("slash");
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "slash");
+        assert_eq!(animator.clip(), "slash");
+        assert!(params.triggers.is_empty());
+
+        // Used up, so the slash isn't restarted on the next frame
+        animator.frame = 1;
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "slash");
+        assert_eq!(animator.frame, 1);
+    }
+
+    #[test]
+    fn triggers_nothing_waits_for_are_dropped() {
+        let library = library();
+        let (mut animator, mut params) = idle_animator(&library);
+
+        params.trigger("jump");
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "idle");
+        assert!(params.triggers.is_empty());
+    }
+
+    #[test]
+    fn finished_waits_for_the_one_shot_clip() {
+        let library = library();
+        let (mut animator, mut params) = idle_animator(&library);
+        params.trigger("slash");
+        step_graph(&library, &mut animator, &mut params);
+
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "slash");
+
+        animator.finished = true;
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "idle");
+        assert_eq!(animator.clip(), "idle");
+        assert!(!animator.finished);
+    }
+
+    #[test]
+    fn one_shot_returns_to_default_and_moves_on_from_there() {
+        let library = library();
+        let (mut animator, mut params) = idle_animator(&library);
+        params.trigger("slash");
+        step_graph(&library, &mut animator, &mut params);
+
+        // Running by the time the slash ends goes through idle to run in the same frame
+        params.set("speed_x", 20.);
+        animator.finished = true;
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "run");
+        assert_eq!(animator.clip(), "run");
+    }
+
+    #[test]
+    fn transition_loops_stop_after_a_few_steps() {
+        let library: AnimationLibrary = ron::from_str(
+            r#"(
+                default: "a",
+                clips: {"a": (frames: [0], fps: 1.0), "b": (frames: [1], fps: 1.0)},
+                states: {
+                    "a": (clip: "a", transitions: [(to: "b")]),
+                    "b": (clip: "b", transitions: [(to: "a")]),
+                },
+            )"#,
+        )
+        .expect("test library should parse");
+        let mut animator = Animator::default();
+        let mut params = AnimationParams::default();
+
+        step_graph(&library, &mut animator, &mut params);
+        assert_eq!(animator.state(), "a");
+    }
+}
@@ -0,0 +1,241 @@
+use crate::animation::AnimationEvent;
+use crate::components::Settings;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_ecs_ldtk::prelude::*;
+
+/// Seconds for one track to fade out while the next fades in
+pub const MUSIC_CROSSFADE_SECS: f32 = 1.5;
+
+/// Sound effects, and level music crossfading between tracks
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioUnlocked>()
+            .add_event::<Sfx>()
+            .add_systems(Startup, load_sfx)
+            .add_systems(
+                Update,
+                (
+                    unlock_audio,
+                    play_animation_sfx,
+                    play_sfx,
+                    play_level_music,
+                    fade_music,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Event, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Sfx {
+    Jump,
+    Land,
+    Slash,
+    Hit,
+    Death,
+    Pickup,
+    Splash,
+}
+
+impl Sfx {
+    const ALL: [Sfx; 7] = [
+        Sfx::Jump,
+        Sfx::Land,
+        Sfx::Slash,
+        Sfx::Hit,
+        Sfx::Death,
+        Sfx::Pickup,
+        Sfx::Splash,
+    ];
+
+    /// Sounds animation frame events can ask for
+    fn from_event(name: &str) -> Option<Sfx> {
+        match name {
+            "jump" => Some(Sfx::Jump),
+            "land" => Some(Sfx::Land),
+            "slash" => Some(Sfx::Slash),
+            "hit" => Some(Sfx::Hit),
+            "death" => Some(Sfx::Death),
+            "pickup" => Some(Sfx::Pickup),
+            "splash" => Some(Sfx::Splash),
+            _ => None,
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Sfx::Jump => "audio/jump.wav",
+            Sfx::Land => "audio/land.wav",
+            Sfx::Slash => "audio/slash.wav",
+            Sfx::Hit => "audio/hit.wav",
+            Sfx::Death => "audio/death.wav",
+            Sfx::Pickup => "audio/pickup.wav",
+            Sfx::Splash => "audio/splash.wav",
+        }
+    }
+}
+
+#[derive(Resource)]
+struct SfxAssets(HashMap<Sfx, Handle<AudioSource>>);
+
+/// Browsers only allow audio after the page has been interacted with,
+/// so nothing plays on the web build until the first input
+#[derive(Resource)]
+pub struct AudioUnlocked(pub bool);
+
+impl Default for AudioUnlocked {
+    fn default() -> Self {
+        AudioUnlocked(!cfg!(target_family = "wasm"))
+    }
+}
+
+#[derive(Component)]
+struct MusicTrack {
+    path: String,
+    /// Fade level between 0 and 1, scaled by the volume settings
+    fade: f32,
+    fading_out: bool,
+}
+
+fn load_sfx(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    cmd.insert_resource(SfxAssets(
+        Sfx::ALL
+            .into_iter()
+            .map(|sfx| (sfx, asset_server.load(sfx.path())))
+            .collect(),
+    ));
+}
+
+fn unlock_audio(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    mut unlocked: ResMut<AudioUnlocked>,
+) {
+    if !unlocked.0
+        && (keys.get_just_pressed().next().is_some()
+            || mouse.get_just_pressed().next().is_some()
+            || touches.any_just_pressed())
+    {
+        unlocked.0 = true;
+    }
+}
+
+fn play_animation_sfx(
+    mut animation_events: EventReader<AnimationEvent>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    for event in animation_events.read() {
+        if let Some(sound) = Sfx::from_event(&event.name) {
+            sfx.send(sound);
+        }
+    }
+}
+
+fn play_sfx(
+    mut cmd: Commands,
+    mut events: EventReader<Sfx>,
+    sfx_assets: Res<SfxAssets>,
+    settings: Res<Settings>,
+    unlocked: Res<AudioUnlocked>,
+) {
+    let mut played = Vec::new();
+    for sfx in events.read() {
+        // The same sound several times in one frame just sounds louder
+        if !unlocked.0 || played.contains(sfx) {
+            continue;
+        }
+        played.push(*sfx);
+        cmd.spawn(AudioBundle {
+            source: sfx_assets.0[sfx].clone(),
+            settings: PlaybackSettings::DESPAWN
+                .with_volume(Volume::new(settings.master_volume * settings.sfx_volume)),
+        });
+    }
+}
+
+fn play_level_music(
+    mut cmd: Commands,
+    asset_server: Res<AssetServer>,
+    unlocked: Res<AudioUnlocked>,
+    level_selection: Res<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut tracks: Query<&mut MusicTrack>,
+) {
+    if !unlocked.0 {
+        return;
+    }
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    let Some(level) = ldtk_project
+        .iter_raw_levels()
+        .enumerate()
+        .find(|(index, level)| level_selection.is_match(&LevelIndices::in_root(*index), level))
+        .map(|(_, level)| level)
+    else {
+        return;
+    };
+    let music = level
+        .get_maybe_string_field("music")
+        .expect("music field should be correctly typed");
+
+    let mut playing = false;
+    for mut track in &mut tracks {
+        let wanted = music.as_ref() == Some(&track.path);
+        // Coming back to a level while its music fades out picks the track back up
+        if track.fading_out == wanted {
+            track.fading_out = !wanted;
+        }
+        playing |= wanted;
+    }
+
+    if let (Some(path), false) = (music, playing) {
+        cmd.spawn((
+            MusicTrack {
+                path: path.clone(),
+                fade: 0.,
+                fading_out: false,
+            },
+            AudioBundle {
+                source: asset_server.load(path.clone()),
+                settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.)),
+            },
+        ));
+    }
+}
+
+fn fade_music(
+    mut cmd: Commands,
+    time: Res<Time<Real>>,
+    settings: Res<Settings>,
+    mut tracks: Query<(Entity, &mut MusicTrack, Option<&AudioSink>)>,
+) {
+    let step = time.delta_seconds() / MUSIC_CROSSFADE_SECS;
+    for (entity, mut track, sink) in &mut tracks {
+        track.fade = if track.fading_out {
+            track.fade - step
+        } else {
+            track.fade + step
+        }
+        .clamp(0., 1.);
+
+        if track.fading_out && track.fade <= 0. {
+            cmd.entity(entity).despawn_recursive();
+            continue;
+        }
+        // The sink only appears once the track has loaded and started playing
+        if let Some(sink) = sink {
+            sink.set_volume(track.fade * settings.master_volume * settings.music_volume);
+        }
+    }
+}
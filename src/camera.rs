@@ -0,0 +1,583 @@
+use crate::animation::TransformInterpolation;
+use crate::components::Settings;
+use crate::pause::{PauseReason, PauseReasons};
+use crate::player::{Climber, GroundDetection, Player};
+use crate::{constants, GameSet};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// Camera follow, zones, level transitions, shake and hit-stop
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraShake>()
+            .init_resource::<HitStopTimer>()
+            .add_event::<Shake>()
+            .add_event::<HitStop>()
+            .register_ldtk_entity::<CameraZoneBundle>("CameraZone")
+            .add_systems(Startup, setup_camera)
+            .add_systems(
+                Update,
+                (camera_follow_player, shake_camera, apply_hit_stop)
+                    .chain()
+                    .in_set(GameSet::Camera),
+            );
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct CameraZoneBundle {
+    #[ldtk_entity]
+    pub camera_zone: CameraZone,
+}
+
+/// Trauma based camera shake, the shake grows with the square of the trauma
+#[derive(Resource, Default)]
+pub struct CameraShake {
+    /// Between 0 and 1, decays over time
+    pub trauma: f32,
+    /// Offset added to the camera this frame, taken back off before the camera follows
+    pub offset: Vec2,
+}
+
+/// Game time is slowed down while this runs
+#[derive(Resource, Default)]
+pub struct HitStopTimer(pub Option<Timer>);
+
+#[derive(Clone, Debug, Component)]
+pub struct CameraController {
+    /// Half size of the box the player can move around in without dragging the camera
+    pub deadzone: Vec2,
+    /// How quickly the camera catches up with where it wants to be, higher is snappier
+    pub damping: f32,
+    /// Seconds of horizontal velocity to look ahead by
+    pub look_ahead: f32,
+    pub max_look_ahead: f32,
+    /// Where the deadzone is centred
+    pub focus: Vec2,
+    pub look_ahead_offset: f32,
+    /// Height to frame, only moved when the player lands or leaves the deadzone vertically
+    pub framing_y: f32,
+    /// Level currently framed, used to notice level changes
+    pub level_iid: Option<String>,
+    /// Size of the view in world units
+    pub view_size: Vec2,
+    pub transition: Option<CameraTransition>,
+}
+
+/// How the camera moves into a level, set with the level's `camera_transition` LDTK field
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum CameraTransitionStyle {
+    Cut,
+    #[default]
+    Pan,
+    /// Pan with the game frozen, like Celeste
+    PanFreeze,
+}
+
+impl From<&ldtk::Level> for CameraTransitionStyle {
+    fn from(level: &ldtk::Level) -> Self {
+        match level
+            .get_enum_field("camera_transition")
+            .map(String::as_str)
+        {
+            Ok("Cut") => CameraTransitionStyle::Cut,
+            Ok("PanFreeze") => CameraTransitionStyle::PanFreeze,
+            _ => CameraTransitionStyle::Pan,
+        }
+    }
+}
+
+/// An in-progress pan and zoom from the previous level's framing to the current one
+#[derive(Clone, Debug)]
+pub struct CameraTransition {
+    pub from_center: Vec2,
+    pub from_size: Vec2,
+    pub timer: Timer,
+    /// Whether game time and physics are paused until the transition finishes
+    pub freeze: bool,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController {
+            deadzone: Vec2::new(12., 24.),
+            damping: 6.,
+            look_ahead: 0.3,
+            max_look_ahead: 40.,
+            focus: Vec2::ZERO,
+            look_ahead_offset: 0.,
+            framing_y: 0.,
+            level_iid: None,
+            view_size: Vec2::ZERO,
+            transition: None,
+        }
+    }
+}
+
+/// Overrides the camera framing while the player is inside it
+#[derive(Clone, PartialEq, Debug, Default, Component)]
+pub struct CameraZone {
+    pub size: Vec2,
+    /// Multiplies the level's view size, below 1 zooms in
+    pub zoom: f32,
+    /// Keep the camera on the zone's centre along this axis
+    pub lock_x: bool,
+    pub lock_y: bool,
+    /// Point the camera sits on, relative to the zone's centre
+    pub pin: Option<Vec2>,
+    /// Overlapping zones are applied from lowest to highest priority
+    pub priority: i32,
+    /// Distance from the edge over which the zone fades in
+    pub blend: f32,
+}
+
+impl CameraZone {
+    /// How strongly the zone applies to a point, 0 outside and 1 once past the blend distance
+    pub fn weight(&self, center: Vec2, point: Vec2) -> f32 {
+        let distance_to_edge = self.size / 2. - (point - center).abs();
+        let inside = distance_to_edge.min_element();
+        if inside <= 0. {
+            0.
+        } else if self.blend <= 0. {
+            1.
+        } else {
+            (inside / self.blend).min(1.)
+        }
+    }
+}
+
+/// Camera zones around the player override the framing, higher priorities on top.
+/// Takes each zone with its centre, and returns the framing's centre and view size
+pub fn apply_camera_zones<'a>(
+    zones: impl IntoIterator<Item = (&'a CameraZone, Vec2)>,
+    player: Vec2,
+    mut desired: Vec2,
+    mut view_size: Vec2,
+) -> (Vec2, Vec2) {
+    let mut active_zones = zones
+        .into_iter()
+        .map(|(zone, center)| (zone, center, zone.weight(center, player)))
+        .filter(|(_, _, weight)| *weight > 0.)
+        .collect::<Vec<_>>();
+    active_zones.sort_by_key(|(zone, _, _)| zone.priority);
+    for (zone, center, weight) in active_zones {
+        view_size = view_size.lerp(view_size * zone.zoom, weight);
+        if let Some(pin) = zone.pin {
+            desired = desired.lerp(center + pin, weight);
+            continue;
+        }
+        if zone.lock_x {
+            desired.x += (center.x - desired.x) * weight;
+        }
+        if zone.lock_y {
+            desired.y += (center.y - desired.y) * weight;
+        }
+    }
+    (desired, view_size)
+}
+
+/// Adds trauma to the camera shake
+#[derive(Event)]
+pub struct Shake {
+    pub trauma: f32,
+}
+
+/// Briefly slows game time to sell an impact
+#[derive(Event)]
+pub struct HitStop {
+    pub seconds: f32,
+    /// Relative speed of game time while frozen
+    pub speed: f32,
+}
+
+pub fn setup_camera(mut cmd: Commands) {
+    cmd.spawn((Camera2dBundle::default(), CameraController::default()));
+}
+
+/// Damped camera follow with a deadzone and look-ahead, kept inside the current level
+pub fn camera_follow_player(
+    time: Res<Time>,
+    real_time: Res<Time<Real>>,
+    mut pause_reasons: ResMut<PauseReasons>,
+    camera_shake: Res<CameraShake>,
+    mut camera_query: Query<
+        (
+            &mut bevy::render::camera::OrthographicProjection,
+            &mut Transform,
+            &mut CameraController,
+        ),
+        Without<Player>,
+    >,
+    player_query: Query<
+        (
+            &Transform,
+            Option<&TransformInterpolation>,
+            &Velocity,
+            &GroundDetection,
+            &Climber,
+        ),
+        With<Player>,
+    >,
+    level_query: Query<(&Transform, &LevelIid), (Without<OrthographicProjection>, Without<Player>)>,
+    zones: Query<(&CameraZone, &GlobalTransform)>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    level_selection: Res<LevelSelection>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    window: Query<&Window>,
+) {
+    let Ok((player_transform, interpolation, velocity, ground, climber)) =
+        player_query.get_single()
+    else {
+        return;
+    };
+    // Follow where the player is drawn, so the two move together
+    let player = interpolation
+        .map_or(player_transform.translation, |interpolation| {
+            interpolation.rendered
+        })
+        .truncate();
+    let (mut orthographic_projection, mut camera_transform, mut controller) =
+        camera_query.single_mut();
+    let controller = &mut *controller;
+
+    // Horizontally, drag the focus along once the player pushes against the deadzone
+    let deadzone = controller.deadzone;
+    controller.focus.x = controller
+        .focus
+        .x
+        .clamp(player.x - deadzone.x, player.x + deadzone.x);
+
+    // Vertically, only reframe on landing, or when the player leaves the deadzone
+    // (long falls, tall ladders), so jumps don't bob the camera
+    if ground.on_ground || climber.climbing {
+        controller.framing_y = player.y;
+    } else {
+        controller.framing_y = controller
+            .framing_y
+            .clamp(player.y - deadzone.y, player.y + deadzone.y);
+    }
+
+    let dt = time.delta_seconds();
+    let smoothing = 1. - (-controller.damping * dt).exp();
+    let look_ahead = (velocity.linvel.x * controller.look_ahead)
+        .clamp(-controller.max_look_ahead, controller.max_look_ahead);
+    controller.look_ahead_offset += (look_ahead - controller.look_ahead_offset) * smoothing;
+
+    let desired = Vec2::new(
+        controller.focus.x + controller.look_ahead_offset,
+        controller.framing_y,
+    );
+    // Work with the framing underneath any shake
+    let current = camera_transform.translation.truncate() - camera_shake.offset;
+
+    // This can be optimised
+    let window = window.single();
+    let aspect_ratio = window.resolution.width() / window.resolution.height();
+
+    let Some((level_min, level_size, level_iid, style)) =
+        level_query.iter().find_map(|(level_transform, level_iid)| {
+            let ldtk_project = ldtk_project_assets
+                .get(ldtk_projects.single())
+                .expect("Project should be loaded if level has spawned");
+
+            let level = ldtk_project
+                .get_raw_level_by_iid(&level_iid.to_string())
+                .expect("Spawned level should exist in LDtk project");
+
+            level_selection
+                .is_match(&LevelIndices::default(), level)
+                .then(|| {
+                    (
+                        level_transform.translation.truncate(),
+                        Vec2::new(level.px_wid as f32, level.px_hei as f32),
+                        level.iid.clone(),
+                        CameraTransitionStyle::from(level),
+                    )
+                })
+        })
+    else {
+        // Nothing to frame until the selected level has spawned
+        let center = current + (desired - current) * smoothing + camera_shake.offset;
+        camera_transform.translation.x = center.x;
+        camera_transform.translation.y = center.y;
+        return;
+    };
+
+    let level_ratio = level_size.x / level_size.y;
+    let view_size = if level_ratio > aspect_ratio {
+        // level is wider than the screen
+        let height = (level_size.y / 9.).round() * 9.;
+        Vec2::new(height * aspect_ratio, height)
+    } else {
+        // level is taller than the screen
+        let width = (level_size.x / 16.).round() * 16.;
+        Vec2::new(width, width / aspect_ratio)
+    };
+
+    let (desired, view_size) = apply_camera_zones(
+        zones
+            .iter()
+            .map(|(zone, transform)| (zone, transform.translation().truncate())),
+        player,
+        desired,
+        view_size,
+    );
+
+    if controller.level_iid.as_ref() != Some(&level_iid) {
+        // A transition interrupted by another level change mustn't leave the game frozen
+        if controller.transition.take().is_some_and(|t| t.freeze) {
+            pause_reasons.remove(PauseReason::CameraPan);
+        }
+        // The first level the camera sees is snapped to, there's nothing to pan from
+        if controller.level_iid.is_some() && style != CameraTransitionStyle::Cut {
+            let freeze = style == CameraTransitionStyle::PanFreeze;
+            if freeze {
+                pause_reasons.insert(PauseReason::CameraPan);
+            }
+            controller.transition = Some(CameraTransition {
+                from_center: current,
+                from_size: controller.view_size,
+                timer: Timer::from_seconds(constants::CAMERA_TRANSITION_SECS, TimerMode::Once),
+                freeze,
+            });
+        }
+        controller.level_iid = Some(level_iid);
+    }
+
+    // While transitioning, aim straight for the framing instead of damping towards it
+    let target = if controller.transition.is_some() {
+        desired
+    } else {
+        current + (desired - current) * smoothing
+    };
+
+    // Keep the view inside the level, centring on any axis it doesn't fill
+    let half_view = view_size / 2.;
+    let min = level_min + half_view;
+    let max = level_min + level_size - half_view;
+    let clamp_axis = |value: f32, min: f32, max: f32| {
+        if min < max {
+            value.clamp(min, max)
+        } else {
+            (min + max) / 2.
+        }
+    };
+    let target = Vec2::new(
+        clamp_axis(target.x, min.x, max.x),
+        clamp_axis(target.y, min.y, max.y),
+    );
+
+    let (center, size) = match controller.transition.as_mut() {
+        Some(transition) => {
+            // Real time, so the transition still plays while the game is frozen
+            transition.timer.tick(real_time.delta());
+            let t = transition.timer.fraction();
+            let eased = t * t * (3. - 2. * t);
+            let framing = (
+                transition.from_center.lerp(target, eased),
+                transition.from_size.lerp(view_size, eased),
+            );
+            if transition.timer.finished() {
+                if transition.freeze {
+                    pause_reasons.remove(PauseReason::CameraPan);
+                }
+                controller.transition = None;
+            }
+            framing
+        }
+        None => (target, view_size),
+    };
+
+    controller.view_size = size;
+    orthographic_projection.viewport_origin = Vec2::splat(0.5);
+    orthographic_projection.scaling_mode = bevy::render::camera::ScalingMode::Fixed {
+        width: size.x,
+        height: size.y,
+    };
+    camera_transform.translation.x = center.x + camera_shake.offset.x;
+    camera_transform.translation.y = center.y + camera_shake.offset.y;
+}
+
+pub fn shake_camera(
+    time: Res<Time<Real>>,
+    settings: Res<Settings>,
+    mut shakes: EventReader<Shake>,
+    mut camera_shake: ResMut<CameraShake>,
+    mut camera_query: Query<&mut Transform, With<CameraController>>,
+) {
+    for shake in shakes.read() {
+        camera_shake.trauma = (camera_shake.trauma + shake.trauma).min(1.);
+    }
+    camera_shake.trauma =
+        (camera_shake.trauma - constants::TRAUMA_DECAY * time.delta_seconds()).max(0.);
+
+    let amount = if settings.screen_shake {
+        camera_shake.trauma * camera_shake.trauma
+    } else {
+        0.
+    };
+    // Layered sines make cheap noise without drawing from the gameplay RNG
+    let t = time.elapsed_seconds();
+    let noise = Vec2::new(
+        (t * 47.).sin() + (t * 73.).sin() * 0.5,
+        (t * 53.).cos() + (t * 89.).sin() * 0.5,
+    ) / 1.5;
+    let offset = noise * constants::MAX_SHAKE_OFFSET * amount;
+
+    // Only move by the change in offset, the camera follow keeps the last offset applied
+    for mut transform in &mut camera_query {
+        transform.translation += (offset - camera_shake.offset).extend(0.);
+    }
+    camera_shake.offset = offset;
+}
+
+pub fn apply_hit_stop(
+    time: Res<Time<Real>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut hit_stops: EventReader<HitStop>,
+    mut hit_stop_timer: ResMut<HitStopTimer>,
+) {
+    for hit_stop in hit_stops.read() {
+        // Keep whichever stop is longer, and whichever is slower
+        let remaining = hit_stop_timer.0.as_ref().map_or(0., Timer::remaining_secs);
+        if hit_stop.seconds > remaining {
+            hit_stop_timer.0 = Some(Timer::from_seconds(hit_stop.seconds, TimerMode::Once));
+        }
+        let speed = if remaining > 0. {
+            virtual_time.relative_speed().min(hit_stop.speed)
+        } else {
+            hit_stop.speed
+        };
+        virtual_time.set_relative_speed(speed);
+    }
+
+    if let Some(timer) = hit_stop_timer.0.as_mut() {
+        if timer.tick(time.delta()).finished() {
+            virtual_time.set_relative_speed(1.);
+            hit_stop_timer.0 = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_weight(zone: &CameraZone, center: Vec2, point: Vec2, expected: f32) {
+        let weight = zone.weight(center, point);
+        assert!(
+            (weight - expected).abs() < EPSILON,
+            "weight at {point} is {weight}, expected {expected}"
+        );
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, EPSILON),
+            "{actual} is not {expected}"
+        );
+    }
+
+    fn zone(size: Vec2, blend: f32) -> CameraZone {
+        CameraZone {
+            size,
+            zoom: 1.,
+            blend,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn weight_is_zero_outside_and_on_the_edge() {
+        let zone = zone(Vec2::new(100., 50.), 10.);
+        assert_weight(&zone, Vec2::ZERO, Vec2::new(60., 0.), 0.);
+        assert_weight(&zone, Vec2::ZERO, Vec2::new(0., -30.), 0.);
+        assert_weight(&zone, Vec2::ZERO, Vec2::new(50., 0.), 0.);
+    }
+
+    #[test]
+    fn weight_fades_in_over_the_blend_distance() {
+        let zone = zone(Vec2::new(100., 50.), 10.);
+        let center = Vec2::new(200., 100.);
+        assert_weight(&zone, center, center + Vec2::new(45., 0.), 0.5);
+        // The nearest edge decides, here the top one
+        assert_weight(&zone, center, center + Vec2::new(0., 22.), 0.3);
+        assert_weight(&zone, center, center + Vec2::new(30., 10.), 1.);
+        assert_weight(&zone, center, center, 1.);
+    }
+
+    #[test]
+    fn weight_without_blend_is_full_anywhere_inside() {
+        let zone = zone(Vec2::new(100., 50.), 0.);
+        assert_weight(&zone, Vec2::ZERO, Vec2::new(49., 24.), 1.);
+        assert_weight(&zone, Vec2::ZERO, Vec2::new(51., 0.), 0.);
+    }
+
+    #[test]
+    fn zones_blend_in_by_weight() {
+        let zone = CameraZone {
+            zoom: 0.5,
+            lock_x: true,
+            ..zone(Vec2::new(100., 100.), 20.)
+        };
+        // 10 inside the left edge, half way through the blend
+        let player = Vec2::new(-40., 0.);
+        let (desired, view_size) = apply_camera_zones(
+            [(&zone, Vec2::ZERO)],
+            player,
+            Vec2::new(-40., 30.),
+            Vec2::new(320., 180.),
+        );
+        assert_near(desired, Vec2::new(-20., 30.));
+        assert_near(view_size, Vec2::new(240., 135.));
+    }
+
+    #[test]
+    fn higher_priority_zones_apply_last() {
+        let low = CameraZone {
+            lock_x: true,
+            priority: 0,
+            ..zone(Vec2::new(400., 400.), 0.)
+        };
+        let high = CameraZone {
+            pin: Some(Vec2::new(0., 50.)),
+            priority: 1,
+            ..zone(Vec2::new(400., 400.), 0.)
+        };
+        let desired = Vec2::new(30., 30.);
+        let view_size = Vec2::new(320., 180.);
+
+        // Whatever order the zones come in, the pin of the higher priority one wins
+        for zones in [
+            [(&low, Vec2::new(-100., 0.)), (&high, Vec2::new(100., 0.))],
+            [(&high, Vec2::new(100., 0.)), (&low, Vec2::new(-100., 0.))],
+        ] {
+            let (framed, _) = apply_camera_zones(zones, Vec2::ZERO, desired, view_size);
+            assert_near(framed, Vec2::new(100., 50.));
+        }
+    }
+
+    #[test]
+    fn zones_away_from_the_player_do_nothing() {
+        let zone = CameraZone {
+            zoom: 2.,
+            lock_y: true,
+            ..zone(Vec2::new(100., 100.), 16.)
+        };
+        let desired = Vec2::new(30., 30.);
+        let view_size = Vec2::new(320., 180.);
+        let (framed, framed_view_size) = apply_camera_zones(
+            [(&zone, Vec2::new(500., 0.))],
+            Vec2::ZERO,
+            desired,
+            view_size,
+        );
+        assert_near(framed, desired);
+        assert_near(framed_view_size, view_size);
+    }
+}
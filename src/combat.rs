@@ -0,0 +1,576 @@
+use crate::animation::{AnimationEvent, AnimationParams};
+use crate::audio::Sfx;
+use crate::camera::{HitStop, Shake};
+use crate::components::{GameRng, SensorBundle, Settings};
+use crate::enemy::Enemy;
+use crate::level::{EntityState, RespawnPoint, WorldState};
+use crate::player::{Items, Player};
+use crate::{constants, ldtk_spawning, GameAssets, GameSet};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+/// Slashing, taking damage, dying, and the pickups enemies drop
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Score>()
+            .add_event::<EnemyKilled>()
+            .register_ldtk_entity::<PickupBundle>("Coin")
+            .register_ldtk_entity::<PickupBundle>("Key")
+            .add_systems(
+                FixedUpdate,
+                (tick_invulnerability, check_touched_enemy, on_dead)
+                    .chain()
+                    .in_set(GameSet::Combat),
+            )
+            .add_systems(
+                Update,
+                (
+                    ldtk_spawning::add_pickup_sprite,
+                    (activate_slash, throw_knife).in_set(GameSet::Input),
+                    (
+                        spawn_slash_sensor,
+                        update_slash_sensor_direction,
+                        update_slash_intersection,
+                        update_knife_hits,
+                    )
+                        .in_set(GameSet::Sensors),
+                    // The hit frame's event comes out of Animation, so the slash lands
+                    // in Combat on the next frame
+                    land_slash_on_hit_frame.after(GameSet::Animation),
+                    (
+                        slash_kill,
+                        despawn_knives,
+                        spawn_enemy_drops,
+                        collect_pickups,
+                    )
+                        .chain()
+                        .in_set(GameSet::Combat),
+                ),
+            );
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct PickupBundle {
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub sensor_bundle: SensorBundle,
+    #[from_entity_instance]
+    pub pickup: Pickup,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+#[derive(Component)]
+pub struct SlashSensor {
+    pub slash_active: bool,
+    pub slash_entity: Entity,
+    pub intersecting_shashables: HashSet<Entity>,
+}
+
+#[derive(Resource, Default)]
+pub struct Score {
+    pub coins: u32,
+    pub points: u32,
+}
+
+#[derive(Clone, Default, Component)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+/// Knives left to throw, one goes with each slash
+#[derive(Clone, Default, Component)]
+pub struct Ammo(pub u32);
+
+/// A thrown knife, flying until it hits something or its timer runs out
+#[derive(Component)]
+pub struct Knife {
+    pub lifetime: Timer,
+    pub struck: HashSet<Entity>,
+    /// Hit a wall or a door, and goes no further
+    pub blocked: bool,
+}
+
+/// Enemies can't hurt the player again until the timer runs out
+#[derive(Clone, Default, Component)]
+pub struct Invulnerable(pub Timer);
+
+#[derive(Clone, Default, Component)]
+pub struct CanDie {
+    pub is_dead: bool,
+    pub dead_animation_timer: Timer,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Component)]
+pub enum Pickup {
+    Coin(u32),
+    Health(u32),
+    Ammo(u32),
+    /// Name of an `Item` enum value from LDTK, added to the player's `Items`
+    Item(String),
+}
+
+impl Default for Pickup {
+    fn default() -> Self {
+        Pickup::Coin(1)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LootDrop {
+    pub pickup: Pickup,
+    /// Chance from 0 to 1 that this drop happens at all
+    pub chance: f32,
+    /// How many pickups to spawn when it does
+    pub rolls: RangeInclusive<u32>,
+}
+
+#[derive(Clone, Debug, Default, Component)]
+pub struct LootTable(pub Vec<LootDrop>);
+
+#[derive(Event)]
+pub struct EnemyKilled {
+    pub enemy: Entity,
+    pub position: Vec2,
+    pub loot_table: LootTable,
+}
+
+/// Starts the slash animation, the slash only lands on its `slash_hit` frame
+pub fn activate_slash(
+    input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut players: Query<&mut AnimationParams, With<Player>>,
+) {
+    if input.just_pressed(settings.key_bindings.slash) {
+        for mut params in &mut players {
+            params.trigger("slash");
+        }
+    }
+}
+
+/// Slashing with ammo also throws a knife the way the player faces
+pub fn throw_knife(
+    mut cmd: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut players: Query<(&GlobalTransform, &Sprite, &mut Ammo), With<Player>>,
+) {
+    if !input.just_pressed(settings.key_bindings.slash) {
+        return;
+    }
+    for (transform, sprite, mut ammo) in &mut players {
+        if ammo.0 == 0 {
+            continue;
+        }
+        ammo.0 -= 1;
+        let direction = if sprite.flip_x { -1. } else { 1. };
+        cmd.spawn((
+            Knife {
+                lifetime: Timer::from_seconds(constants::KNIFE_LIFETIME_SECS, TimerMode::Once),
+                struck: HashSet::new(),
+                blocked: false,
+            },
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::SILVER,
+                    custom_size: Some(Vec2::new(8., 2.)),
+                    ..default()
+                },
+                transform: Transform::from_translation(
+                    transform.translation().truncate().extend(9.),
+                ),
+                ..default()
+            },
+            Collider::cuboid(4., 1.),
+            Sensor,
+            RigidBody::KinematicVelocityBased,
+            Velocity::linear(Vec2::new(direction * constants::KNIFE_SPEED, 0.)),
+            ActiveEvents::COLLISION_EVENTS,
+            ActiveCollisionTypes::all(),
+        ));
+    }
+}
+
+/// Arms the slash sensor for the one frame after the animation reaches its hit frame
+pub fn land_slash_on_hit_frame(
+    mut animation_events: EventReader<AnimationEvent>,
+    mut query: Query<&mut SlashSensor>,
+) {
+    let hits: Vec<Entity> = animation_events
+        .read()
+        .filter(|event| event.name == "slash_hit")
+        .map(|event| event.entity)
+        .collect();
+    for mut sensor in &mut query {
+        sensor.slash_active = hits.contains(&sensor.slash_entity);
+    }
+}
+
+pub fn spawn_slash_sensor(
+    mut commands: Commands,
+    slash_for: Query<(Entity, &Collider), Added<Player>>,
+    game_assets: Res<GameAssets>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    for (entity, shape) in &slash_for {
+        if let Some(round) = shape.as_capsule() {
+            let half_extents_x = round.radius();
+            let _half_extents_y = round.half_height() + half_extents_x;
+
+            let detector_shape = Collider::cuboid(8., 8.);
+
+            let sensor_translation = Vec3::new(half_extents_x * 2., 0., 5.);
+
+            commands.entity(entity).with_children(|builder| {
+                builder
+                    .spawn_empty()
+                    .insert(ActiveEvents::COLLISION_EVENTS)
+                    .insert(ActiveCollisionTypes::all())
+                    .insert(detector_shape)
+                    .insert(Sensor)
+                    .insert(SpriteSheetBundle {
+                        sprite: Sprite {
+                            custom_size: Some(Vec2 { x: 16., y: 16. }),
+                            ..default()
+                        },
+                        texture: game_assets.slash.clone(),
+                        atlas: TextureAtlas {
+                            layout: texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
+                                Vec2 { x: 32.0, y: 32.0 },
+                                4,
+                                1,
+                                None,
+                                None,
+                            )),
+                            index: 1,
+                        },
+                        transform: Transform::from_translation(sensor_translation).with_scale(
+                            Vec3 {
+                                x: 1.,
+                                y: 1.,
+                                z: 8.,
+                            },
+                        ),
+                        ..default()
+                    })
+                    .insert(SlashSensor {
+                        slash_active: false,
+                        slash_entity: entity,
+                        intersecting_shashables: HashSet::new(),
+                    });
+            });
+        }
+    }
+}
+
+pub fn update_slash_sensor_direction(
+    query: Query<&Sprite, With<Player>>,
+    mut children: Query<&mut Transform, (With<SlashSensor>, With<Parent>)>,
+) {
+    if let Ok(sprite) = query.get_single() {
+        for mut transform in &mut children {
+            if (sprite.flip_x && transform.translation.x > 0.0)
+                || (!sprite.flip_x && transform.translation.x < 0.0)
+            {
+                transform.translation.x *= -1.0;
+            }
+        }
+    }
+}
+
+pub fn update_slash_intersection(
+    mut slashes: Query<&mut SlashSensor>,
+    enemies: Query<Entity, With<Enemy>>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.read() {
+        match collision {
+            CollisionEvent::Started(collider_a, collider_b, _) => {
+                if let (Ok(mut slash), Ok(enemy)) =
+                    (slashes.get_mut(*collider_a), enemies.get(*collider_b))
+                {
+                    slash.intersecting_shashables.insert(enemy);
+                }
+                if let (Ok(mut slash), Ok(enemy)) =
+                    (slashes.get_mut(*collider_b), enemies.get(*collider_a))
+                {
+                    slash.intersecting_shashables.insert(enemy);
+                };
+            }
+            CollisionEvent::Stopped(collider_a, collider_b, _) => {
+                if let (Ok(mut slash), Ok(enemy)) =
+                    (slashes.get_mut(*collider_a), enemies.get(*collider_b))
+                {
+                    slash.intersecting_shashables.remove(&enemy);
+                }
+
+                if let (Ok(mut slash), Ok(enemy)) =
+                    (slashes.get_mut(*collider_b), enemies.get(*collider_a))
+                {
+                    slash.intersecting_shashables.remove(&enemy);
+                }
+            }
+        }
+    }
+}
+
+/// Knives strike enemies, and stop at anything solid
+pub fn update_knife_hits(
+    mut knives: Query<&mut Knife>,
+    enemies: Query<(), With<Enemy>>,
+    solids: Query<(), (Without<Sensor>, Without<Player>, Without<Pickup>)>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.read() {
+        let CollisionEvent::Started(collider_a, collider_b, _) = collision else {
+            continue;
+        };
+        for (knife, other) in [(collider_a, collider_b), (collider_b, collider_a)] {
+            let Ok(mut knife) = knives.get_mut(*knife) else {
+                continue;
+            };
+            if enemies.contains(*other) {
+                knife.struck.insert(*other);
+            } else if solids.contains(*other) {
+                knife.blocked = true;
+            }
+        }
+    }
+}
+
+pub fn despawn_knives(mut cmd: Commands, time: Res<Time>, mut knives: Query<(Entity, &mut Knife)>) {
+    for (entity, mut knife) in &mut knives {
+        knife.lifetime.tick(time.delta());
+        if knife.blocked || !knife.struck.is_empty() || knife.lifetime.finished() {
+            cmd.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn slash_kill(
+    mut cmd: Commands,
+    query: Query<&SlashSensor>,
+    knives: Query<&Knife>,
+    enemies: Query<(&GlobalTransform, &LootTable, Option<&EntityInstance>), With<Enemy>>,
+    mut score: ResMut<Score>,
+    mut world_state: ResMut<WorldState>,
+    mut killed: EventWriter<EnemyKilled>,
+    mut shakes: EventWriter<Shake>,
+    mut hit_stops: EventWriter<HitStop>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    let struck = query
+        .iter()
+        .filter(|slash_sensor| slash_sensor.slash_active)
+        .flat_map(|slash_sensor| &slash_sensor.intersecting_shashables)
+        .chain(knives.iter().flat_map(|knife| &knife.struck));
+    for entity in struck {
+        if let (Some(enemy), Ok((transform, loot_table, entity_instance))) =
+            (cmd.get_entity(*entity), enemies.get(*entity))
+        {
+            enemy.despawn_recursive();
+            score.points += constants::ENEMY_KILL_POINTS;
+            world_state.record(entity_instance, EntityState::Killed);
+            killed.send(EnemyKilled {
+                enemy: *entity,
+                position: transform.translation().truncate(),
+                loot_table: loot_table.clone(),
+            });
+            shakes.send(Shake { trauma: 0.3 });
+            sfx.send(Sfx::Hit);
+            hit_stops.send(HitStop {
+                seconds: 0.06,
+                speed: 0.05,
+            });
+        }
+    }
+}
+
+pub fn spawn_enemy_drops(
+    mut cmd: Commands,
+    mut killed: EventReader<EnemyKilled>,
+    mut rng: ResMut<GameRng>,
+) {
+    for EnemyKilled {
+        position,
+        loot_table,
+        ..
+    } in killed.read()
+    {
+        for drop in &loot_table.0 {
+            if rng.next_f32() >= drop.chance {
+                continue;
+            }
+            for _ in 0..rng.range(drop.rolls.clone()) {
+                // Pop the pickup out of the enemy in a random upwards arc
+                let launch = Vec2::new(rng.next_f32() * 160. - 80., 150. + rng.next_f32() * 100.);
+                cmd.spawn((
+                    drop.pickup.clone(),
+                    SpriteBundle {
+                        transform: Transform::from_translation(position.extend(9.)),
+                        ..default()
+                    },
+                    Collider::ball(3.),
+                    RigidBody::Dynamic,
+                    Velocity::linear(launch),
+                    LockedAxes::ROTATION_LOCKED,
+                    Restitution::coefficient(0.4),
+                    ActiveEvents::COLLISION_EVENTS,
+                    SolverGroups::new(constants::PICKUP_GROUP, Group::ALL),
+                ));
+            }
+        }
+    }
+}
+
+pub fn collect_pickups(
+    mut cmd: Commands,
+    mut player: Query<(&mut Health, &mut Ammo, &mut Items), With<Player>>,
+    pickups: Query<(&Pickup, Option<&EntityInstance>)>,
+    mut score: ResMut<Score>,
+    mut world_state: ResMut<WorldState>,
+    mut collisions: EventReader<CollisionEvent>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    let mut collected = HashSet::new();
+    for collision in collisions.read() {
+        if let CollisionEvent::Started(collider_a, collider_b, _) = collision {
+            let pickup_entity = if player.contains(*collider_a) {
+                *collider_b
+            } else if player.contains(*collider_b) {
+                *collider_a
+            } else {
+                continue;
+            };
+            let (Ok((pickup, entity_instance)), Ok((mut health, mut ammo, mut items))) =
+                (pickups.get(pickup_entity), player.get_single_mut())
+            else {
+                continue;
+            };
+            if !collected.insert(pickup_entity) {
+                continue;
+            }
+            match pickup {
+                Pickup::Coin(amount) => {
+                    score.coins += amount;
+                    score.points += amount * constants::COIN_POINTS;
+                }
+                Pickup::Health(amount) => {
+                    health.current = (health.current + amount).min(health.max);
+                }
+                Pickup::Ammo(amount) => ammo.0 += amount,
+                Pickup::Item(item) => {
+                    if !items.0.contains(item) {
+                        items.0.push(item.clone());
+                    }
+                }
+            }
+            world_state.record(entity_instance, EntityState::Collected);
+            sfx.send(Sfx::Pickup);
+            cmd.entity(pickup_entity).despawn_recursive();
+        }
+    }
+}
+
+/// Blinks the player while they can't be hurt, and ends it once the timer runs out
+pub fn tick_invulnerability(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut Sprite)>,
+) {
+    for (entity, mut invulnerable, mut sprite) in &mut query {
+        let timer = invulnerable.0.tick(time.delta());
+        if timer.finished() {
+            sprite.color.set_a(1.0);
+            cmd.entity(entity).remove::<Invulnerable>();
+        } else {
+            sprite.color.set_a(if timer.elapsed_secs() % 0.2 < 0.1 {
+                0.4
+            } else {
+                1.0
+            });
+        }
+    }
+}
+
+pub fn check_touched_enemy(
+    mut cmd: Commands,
+    mut player: Query<
+        (
+            Entity,
+            &mut CanDie,
+            &mut Health,
+            Option<&mut AnimationParams>,
+            Has<Invulnerable>,
+        ),
+        With<Player>,
+    >,
+    enemies: Query<(Entity, &GlobalTransform), With<Enemy>>,
+    mut collisions: EventReader<CollisionEvent>,
+    mut shakes: EventWriter<Shake>,
+    mut hit_stops: EventWriter<HitStop>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    let mut touched = false;
+    for collision in collisions.read() {
+        if let CollisionEvent::Started(collider_a, collider_b, _) = collision {
+            touched |= (player.contains(*collider_a) && enemies.contains(*collider_b))
+                || (player.contains(*collider_b) && enemies.contains(*collider_a));
+        }
+    }
+    let Ok((entity, mut can_die, mut health, params, invulnerable)) = player.get_single_mut()
+    else {
+        return;
+    };
+    if !touched || invulnerable {
+        return;
+    }
+
+    if let Some(mut params) = params {
+        params.trigger("hit");
+    }
+    shakes.send(Shake { trauma: 0.5 });
+    sfx.send(Sfx::Hit);
+    hit_stops.send(HitStop {
+        seconds: 0.12,
+        speed: 0.1,
+    });
+    health.current = health.current.saturating_sub(1);
+    if health.current == 0 {
+        can_die.is_dead = true;
+    } else {
+        cmd.entity(entity).insert(Invulnerable(Timer::from_seconds(
+            constants::PLAYER_INVULNERABLE_SECS,
+            TimerMode::Once,
+        )));
+    }
+}
+
+pub fn on_dead(
+    mut query: Query<(&mut CanDie, &mut Health, &mut Transform), With<Player>>,
+    mut level_selection: ResMut<LevelSelection>,
+    respawn_point: Res<RespawnPoint>,
+    mut shakes: EventWriter<Shake>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    for (mut can_die, mut health, mut transform) in &mut query {
+        if can_die.is_dead {
+            shakes.send(Shake { trauma: 0.8 });
+            sfx.send(Sfx::Death);
+            transform.translation = respawn_point.position;
+            *level_selection = respawn_point
+                .level_iid
+                .clone()
+                .map_or(LevelSelection::index(0), LevelSelection::iid);
+            can_die.is_dead = false;
+            health.current = health.max;
+        }
+    }
+}
@@ -1,11 +1,10 @@
 use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
-
-use std::collections::{HashMap, HashSet};
-
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 
-// Bundles:
+// Bundles shared by the gameplay plugins:
 
 #[derive(Clone, Default, Bundle, LdtkIntCell)]
 pub struct SensorBundle {
@@ -25,159 +24,235 @@ pub struct ColliderBundle {
     pub friction: Friction,
     pub density: ColliderMassProperties,
     pub active_events: ActiveEvents,
+    pub offset: BodyOffset,
 }
 
-#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
-pub struct WallBundle {
-    wall: Wall,
-}
+// Components
 
-#[derive(Clone, Default, Bundle, LdtkIntCell)]
-pub struct LadderBundle {
-    #[from_int_grid_cell]
-    pub sensor_bundle: SensorBundle,
-    pub climbable: Climbable,
-}
+#[derive(Copy, Clone, Debug, Default, Component)]
+pub struct IsLdtkEntity;
 
-#[derive(Clone, Default, Bundle, LdtkIntCell)]
-pub struct WaterBundle {
-    #[from_int_grid_cell]
-    pub sensor_bundle: SensorBundle,
-    pub water: Water,
+/// Moves a body away from where LDTK placed it, once it spawns
+#[derive(Copy, Clone, Debug, Default, Component)]
+pub struct BodyOffset(pub Vec2);
+
+// Resources
+
+/// Player preferences, stored apart from the save slots so they apply to all of them
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub vsync: bool,
+    pub window_mode: WindowModeSetting,
+    /// Window size while windowed, the web build always fills its canvas
+    pub resolution: Vec2,
+    /// Accessibility option, hit-stop still plays without it
+    pub screen_shake: bool,
+    /// Show the play time on the HUD
+    pub show_timer: bool,
+    /// Show the speedrun timer and splits
+    pub speedrun_timer: bool,
+    /// Volume buses between 0 and 1, music and sfx are scaled by master
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub key_bindings: KeyBindings,
+    pub language: Language,
 }
 
-#[derive(Default, Bundle, LdtkEntity)]
-pub struct PlayerBundle {
-    #[sprite_sheet_bundle]
-    sprite_sheet_bundle: SpriteSheetBundle,
-
-    #[from_entity_instance]
-    pub collider_bundle: ColliderBundle,
-    pub player: Player,
-    #[worldly]
-    pub worldly: Worldly,
-    pub climber: Climber,
-    pub ground_detection: GroundDetection,
-
-    // Animation components
-    animation_state: AnimationState,
-
-    // Build Items Component manually by using `impl From<&EntityInstance>`
-    #[from_entity_instance]
-    items: Items,
-
-    // The whole EntityInstance can be stored directly as an EntityInstance component
-    #[from_entity_instance]
-    entity_instance: EntityInstance,
-    pub entity: IsLdtkEntity,
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            vsync: false,
+            window_mode: WindowModeSetting::default(),
+            resolution: crate::constants::BASE_RES,
+            screen_shake: true,
+            show_timer: false,
+            speedrun_timer: false,
+            master_volume: 1.,
+            music_volume: 0.6,
+            sfx_volume: 0.8,
+            key_bindings: KeyBindings::default(),
+            language: Language::default(),
+        }
+    }
 }
 
-#[derive(Default, Bundle, LdtkEntity)]
-pub struct MobBundle {
-    #[sprite_sheet_bundle]
-    pub sprite_sheet_bundle: SpriteSheetBundle,
-    #[from_entity_instance]
-    pub collider_bundle: ColliderBundle,
-    pub enemy: Enemy,
-    #[ldtk_entity]
-    pub patrol: Patrol,
-    pub entity: IsLdtkEntity,
-    // Animation components
-    animation_state: AnimationState,
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum WindowModeSetting {
+    #[default]
+    Windowed,
+    BorderlessFullscreen,
+    Fullscreen,
 }
 
-// Components
-
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Water;
-
-#[derive(Component)]
-pub struct PlayerAnimations {
-    pub idle: benimator::Animation,
-    pub walk: benimator::Animation,
-    pub jump_prep: benimator::Animation,
-    pub jump_up: benimator::Animation,
-    pub jump_down: benimator::Animation,
-    pub jump_land: benimator::Animation,
-    pub hit: benimator::Animation,
-    pub slash: benimator::Animation,
-    pub punch: benimator::Animation,
-    pub run: benimator::Animation,
-    pub climb: benimator::Animation,
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub jump: KeyCode,
+    pub slash: KeyCode,
+    pub interact: KeyCode,
 }
 
-#[derive(Component, Clone)]
-pub struct PatrolAnimation(pub benimator::Animation);
-
-#[derive(Default, Component, Deref, DerefMut)]
-pub struct AnimationState(pub benimator::State);
-
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Climbable;
-
-#[derive(Component)]
-pub struct GroundSensor {
-    pub ground_detection_entity: Entity,
-    pub intersecting_ground_entities: HashSet<Entity>,
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            up: KeyCode::KeyW,
+            down: KeyCode::KeyS,
+            jump: KeyCode::Space,
+            slash: KeyCode::KeyJ,
+            interact: KeyCode::KeyE,
+        }
+    }
 }
 
-#[derive(Component)]
-pub struct SlashSensor {
-    pub slash_active: bool,
-    pub slash_entity: Entity,
-    pub intersecting_shashables: HashSet<Entity>,
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::Up => self.up,
+            Action::Down => self.down,
+            Action::Jump => self.jump,
+            Action::Slash => self.slash,
+            Action::Interact => self.interact,
+        }
+    }
+
+    pub fn key_mut(&mut self, action: Action) -> &mut KeyCode {
+        match action {
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Jump => &mut self.jump,
+            Action::Slash => &mut self.slash,
+            Action::Interact => &mut self.interact,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Wall;
-
-#[derive(Resource, Default)]
-pub struct GameTouches(pub Vec<Vec2>);
-
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Player;
-
-#[derive(Clone, PartialEq, Debug, Default, Component)]
-pub struct Climber {
-    pub climbing: bool,
-    pub intersecting_climbables: HashMap<Entity, GlobalTransform>,
+/// Gameplay inputs, bound to keys by `KeyBindings`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    Jump,
+    Slash,
+    Interact,
 }
 
-#[derive(Clone, Component, Debug, Eq, Default, PartialEq)]
-pub struct Items(pub Vec<String>);
-
-#[derive(Clone, Default, Component)]
-pub struct GroundDetection {
-    pub on_ground: bool,
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::Left,
+        Action::Right,
+        Action::Up,
+        Action::Down,
+        Action::Jump,
+        Action::Slash,
+        Action::Interact,
+    ];
 }
 
-#[derive(Clone, Default, Component)]
-pub struct AccelerationStat(pub f32);
-
-#[derive(Clone, Default, Component)]
-pub struct MaxSpeedStat(pub Vec2);
-
-#[derive(Clone, Default, Component)]
-pub struct JumpForceStat(pub f32);
-
-#[derive(Clone, Default, Component)]
-pub struct FakeGroundFrictionStat(pub f32);
-
-#[derive(Clone, Default, Component)]
-pub struct CanDie {
-    pub is_dead: bool,
-    pub dead_animation_timer: Timer,
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    English,
+    French,
 }
 
-#[derive(Clone, PartialEq, Debug, Default, Component)]
-pub struct Patrol {
-    pub points: Vec<Vec2>,
-    pub index: usize,
-    pub forward: bool,
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::French];
+
+    /// Translates UI text, which is written in English in the code
+    pub fn tr(self, text: &'static str) -> &'static str {
+        match self {
+            Language::English => text,
+            Language::French => match text {
+                "SETTINGS" => "PARAMÈTRES",
+                "VSYNC" => "SYNCHRO VERTICALE",
+                "WINDOW MODE" => "MODE FENÊTRE",
+                "WINDOWED" => "FENÊTRÉ",
+                "BORDERLESS" => "SANS BORDURE",
+                "FULLSCREEN" => "PLEIN ÉCRAN",
+                "RESOLUTION" => "RÉSOLUTION",
+                "MASTER VOLUME" => "VOLUME GÉNÉRAL",
+                "MUSIC VOLUME" => "VOLUME MUSIQUE",
+                "SFX VOLUME" => "VOLUME EFFETS",
+                "SCREEN SHAKE" => "TREMBLEMENT",
+                "SHOW TIMER" => "AFFICHER LE CHRONO",
+                "SPEEDRUN TIMER" => "CHRONO SPEEDRUN",
+                "LANGUAGE" => "LANGUE",
+                "LEFT" => "GAUCHE",
+                "RIGHT" => "DROITE",
+                "UP" => "HAUT",
+                "DOWN" => "BAS",
+                "JUMP" => "SAUT",
+                "SLASH" => "ATTAQUE",
+                "INTERACT" => "INTERAGIR",
+                "BACK" => "RETOUR",
+                "ON" => "OUI",
+                "OFF" => "NON",
+                "PRESS A KEY" => "APPUYEZ SUR UNE TOUCHE",
+                "COINS" => "PIÈCES",
+                "SCORE" => "SCORE",
+                "AMMO" => "MUNITIONS",
+                "ENGLISH" => "ANGLAIS",
+                "FRENCH" => "FRANÇAIS",
+                "GHOST: ON" => "FANTÔME : OUI",
+                "GHOST: OFF" => "FANTÔME : NON",
+                "NO GHOST YET" => "PAS ENCORE DE FANTÔME",
+                "ARROWS: SELECT   ENTER: WARP   G: GHOST   M: CLOSE" => {
+                    "FLÈCHES : CHOISIR   ENTRÉE : TÉLÉPORTER   G : FANTÔME   M : FERMER"
+                }
+                _ => text,
+            },
+        }
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Enemy;
+/// Small xorshift generator so drops don't depend on an external rand crate
+#[derive(Resource)]
+pub struct GameRng(u64);
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        GameRng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform float in `0.0..1.0`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in the range, or its start when the range is empty
+    pub fn range(&mut self, range: RangeInclusive<u32>) -> u32 {
+        if range.is_empty() {
+            return *range.start();
+        }
+        let span = u64::from(range.end() - range.start()) + 1;
+        range.start() + (self.next_u64() % span) as u32
+    }
+}
 
-#[derive(Copy, Clone, Debug, Default, Component)]
-pub struct IsLdtkEntity;
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::from_seed(crate::constants::RNG_SEED)
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use bevy_rapier2d::prelude::Group;
 
 // Window stuff
 pub const BASE_RES: Vec2 = Vec2 {
@@ -15,8 +16,19 @@ pub enum CollideEnums {
 }
 
 // Physics engine stuff
+/// Gameplay and physics steps per second, whatever the frame rate
+pub const FIXED_TIMESTEP_HZ: f64 = 60.0;
 pub const GRAVITY: f32 = -2000.0;
 pub const PIXELS_PER_METER: f32 = 100.0;
+pub const PLAYER_GROUP: Group = Group::GROUP_1;
+pub const PICKUP_GROUP: Group = Group::GROUP_2;
+
+// Camera stuff
+pub const CAMERA_TRANSITION_SECS: f32 = 0.5;
+/// Furthest the camera moves from its framing at full trauma, in pixels
+pub const MAX_SHAKE_OFFSET: f32 = 8.0;
+/// Trauma lost per second
+pub const TRAUMA_DECAY: f32 = 1.5;
 
 // Game stuff
 
@@ -25,3 +37,14 @@ pub const DEFAULT_SPAWN: Vec3 = Vec3 {
     y: -170.0,
     z: 10.0,
 };
+
+pub const PLAYER_MAX_HEALTH: u32 = 3;
+/// How long the player can't be hurt again after an enemy touches them
+pub const PLAYER_INVULNERABLE_SECS: f32 = 1.0;
+/// Thrown knives fly straight, in pixels per second
+pub const KNIFE_SPEED: f32 = 320.0;
+pub const KNIFE_LIFETIME_SECS: f32 = 0.6;
+pub const ENEMY_KILL_POINTS: u32 = 100;
+pub const COIN_POINTS: u32 = 10;
+pub const WAVE_CLEARED_POINTS: u32 = 500;
+pub const RNG_SEED: u64 = 0x2545_f491_4f6c_dd1d;
@@ -6,17 +6,22 @@ use bevy_rapier2d::render::DebugRenderMode;
 use bevy_rapier2d::render::DebugRenderStyle;
 use bevy_rapier2d::render::RapierDebugRenderPlugin;
 
-pub fn add_plugin(app: &mut App) {
-    app.add_systems(Startup, setup_fps_counter)
-        .add_systems(Update, (fps_text_update_system, fps_counter_showhide))
-        .add_plugins((
-            FrameTimeDiagnosticsPlugin,
-            RapierDebugRenderPlugin {
-                enabled: false,
-                style: DebugRenderStyle { ..default() },
-                mode: DebugRenderMode::all(),
-            },
-        ));
+/// FPS counter and physics debug rendering
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_fps_counter)
+            .add_systems(Update, (fps_text_update_system, fps_counter_showhide))
+            .add_plugins((
+                FrameTimeDiagnosticsPlugin,
+                RapierDebugRenderPlugin {
+                    enabled: false,
+                    style: DebugRenderStyle { ..default() },
+                    mode: DebugRenderMode::all(),
+                },
+            ));
+    }
 }
 
 /// Marker to find the container entity so we can show/hide the FPS counter
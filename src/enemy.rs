@@ -0,0 +1,230 @@
+use crate::animation::{self, AnimationLibrary};
+use crate::audio::Sfx;
+use crate::combat::{LootTable, Score};
+use crate::components::{ColliderBundle, IsLdtkEntity};
+use crate::level::{Signal, SignalKind, SignalReceiver};
+use crate::stats::{CharacterKind, StatOverrides};
+use crate::{constants, ldtk_spawning, GameAssets, GameSet};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+/// Patrolling enemies and the spawners that send them in waves
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WaveCleared>()
+            .register_ldtk_entity::<MobBundle>("Snake")
+            .register_ldtk_entity::<SpawnerBundle>("Spawner")
+            .add_systems(FixedUpdate, patrol.in_set(GameSet::Movement))
+            .add_systems(
+                Update,
+                (
+                    run_spawners
+                        .after(ldtk_spawning::apply_world_state)
+                        .after(crate::level::receive_signals),
+                    award_cleared_waves,
+                )
+                    .chain()
+                    .in_set(GameSet::Combat),
+            );
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct MobBundle {
+    #[sprite_sheet_bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    pub enemy: Enemy,
+    #[ldtk_entity]
+    pub patrol: Patrol,
+    #[from_entity_instance]
+    pub loot_table: LootTable,
+    #[from_entity_instance]
+    pub character_kind: CharacterKind,
+    #[from_entity_instance]
+    pub stat_overrides: StatOverrides,
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+    pub entity: IsLdtkEntity,
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct SpawnerBundle {
+    #[ldtk_entity]
+    pub spawner: Spawner,
+    pub signal_receiver: SignalReceiver,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Component)]
+pub struct Patrol {
+    pub points: Vec<Vec2>,
+    pub index: usize,
+    pub forward: bool,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Enemy;
+
+/// Walking speed between patrol points, in pixels per second
+#[derive(Copy, Clone, PartialEq, Debug, Default, Component, Deserialize)]
+pub struct PatrolSpeedStat {
+    pub speed: f32,
+    /// Speed of the push off a patrol point after turning around
+    pub turn_speed: f32,
+}
+
+/// Keeps spawning `mob` every `timer` tick while fewer than `max_alive` of its mobs are around
+#[derive(Clone, Debug, Default, Component)]
+pub struct Spawner {
+    /// Used to build the mob the same way LDTK would have
+    pub mob: EntityInstance,
+    pub patrol: Vec<Vec2>,
+    pub timer: Timer,
+    pub max_alive: usize,
+    /// `None` spawns forever
+    pub total: Option<u32>,
+    pub spawned: u32,
+    /// Only spawn while powered by a signal
+    pub on_trigger: bool,
+    /// Signalled `On` once the wave is cleared
+    pub targets: Vec<String>,
+    pub cleared: bool,
+}
+
+#[derive(Copy, Clone, Debug, Component)]
+pub struct SpawnedBy(pub Entity);
+
+#[derive(Event)]
+pub struct WaveCleared {
+    pub spawner: Entity,
+}
+
+pub fn patrol(mut query: Query<(&mut Transform, &mut Velocity, &mut Patrol, &PatrolSpeedStat)>) {
+    for (mut transform, mut velocity, mut patrol, speed) in &mut query {
+        if patrol.points.len() <= 1 {
+            continue;
+        }
+
+        let mut new_velocity = (patrol.points[patrol.index] - transform.translation.truncate())
+            .normalize()
+            * speed.speed;
+
+        if new_velocity.dot(velocity.linvel) < 0. {
+            if patrol.index == 0 {
+                patrol.forward = true;
+            } else if patrol.index == patrol.points.len() - 1 {
+                patrol.forward = false;
+            }
+
+            transform.translation.x = patrol.points[patrol.index].x;
+            transform.translation.y = patrol.points[patrol.index].y;
+
+            if patrol.forward {
+                patrol.index += 1;
+            } else {
+                patrol.index -= 1;
+            }
+
+            new_velocity = (patrol.points[patrol.index] - transform.translation.truncate())
+                .normalize()
+                * speed.turn_speed;
+        }
+
+        velocity.linvel = new_velocity;
+    }
+}
+
+pub fn run_spawners(
+    mut cmd: Commands,
+    time: Res<Time>,
+    game_assets: Res<GameAssets>,
+    asset_server: Res<AssetServer>,
+    libraries: Res<Assets<AnimationLibrary>>,
+    mut spawners: Query<(Entity, &mut Spawner, &SignalReceiver, &Transform, &Parent)>,
+    spawned: Query<&SpawnedBy>,
+    mut cleared: EventWriter<WaveCleared>,
+    mut signals: EventWriter<Signal>,
+) {
+    for (entity, mut spawner, receiver, transform, parent) in &mut spawners {
+        if spawner.cleared || (spawner.on_trigger && !receiver.powered) {
+            continue;
+        }
+        let alive = spawned
+            .iter()
+            .filter(|spawned_by| spawned_by.0 == entity)
+            .count();
+        let exhausted = spawner.total.is_some_and(|total| spawner.spawned >= total);
+
+        if exhausted {
+            if alive == 0 {
+                spawner.cleared = true;
+                cleared.send(WaveCleared { spawner: entity });
+                for target in &spawner.targets {
+                    signals.send(Signal {
+                        target: target.clone(),
+                        kind: SignalKind::On,
+                    });
+                }
+            }
+            continue;
+        }
+
+        if !spawner.timer.tick(time.delta()).just_finished() || alive >= spawner.max_alive {
+            continue;
+        }
+        spawner.spawned += 1;
+
+        // Show the mob's first frame until its animator takes over
+        let character_kind = CharacterKind::from(&spawner.mob);
+        let index = libraries
+            .get(&asset_server.load(animation::library_path(&character_kind)))
+            .and_then(AnimationLibrary::first_frame)
+            .unwrap_or_default();
+
+        // Spawn next to the spawner, under the same layer entity, so the mob
+        // shares its coordinates and unloads together with the level
+        let mob = MobBundle {
+            sprite_sheet_bundle: SpriteSheetBundle {
+                texture: game_assets.characters.clone(),
+                atlas: TextureAtlas {
+                    layout: game_assets.characters_layout.clone(),
+                    index,
+                },
+                transform: Transform::from_translation(transform.translation),
+                ..default()
+            },
+            collider_bundle: ColliderBundle::from(&spawner.mob),
+            loot_table: LootTable::from(&spawner.mob),
+            character_kind,
+            stat_overrides: StatOverrides::from(&spawner.mob),
+            patrol: Patrol {
+                points: spawner.patrol.clone(),
+                index: 1,
+                forward: true,
+            },
+            ..default()
+        };
+        cmd.entity(parent.get()).with_children(|layer| {
+            layer.spawn((mob, SpawnedBy(entity)));
+        });
+    }
+}
+
+/// Clearing a wave is worth more than the mobs in it
+pub fn award_cleared_waves(
+    mut cleared: EventReader<WaveCleared>,
+    mut score: ResMut<Score>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    for _ in cleared.read() {
+        score.points += constants::WAVE_CLEARED_POINTS;
+        sfx.send(Sfx::Pickup);
+    }
+}
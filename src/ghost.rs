@@ -0,0 +1,271 @@
+use crate::map::selected_level_iid;
+use crate::player::Player;
+use crate::storage;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Further than the player can move in a frame, so the attempt was a death or a warp
+const MAX_FRAME_STEP: f32 = 48.0;
+const GHOST_ALPHA: f32 = 0.4;
+
+/// Recording the best run through each level and racing its ghost
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Ghosts>()
+            .init_resource::<GhostRecorder>()
+            .add_systems(
+                Update,
+                (load_ghosts, record_ghost_frames, spawn_ghosts, play_ghosts).chain(),
+            );
+    }
+}
+
+/// One frame of the player, as the ghost shows it
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct GhostFrame {
+    /// Seconds since entering the level
+    pub time: f32,
+    pub position: Vec2,
+    pub index: usize,
+    pub flip_x: bool,
+}
+
+/// The player's way through a level, from entering it to leaving it
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GhostRun {
+    pub frames: Vec<GhostFrame>,
+}
+
+impl GhostRun {
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map_or(0., |frame| frame.time)
+    }
+
+    /// The frame shown at `time`, with the position interpolated between samples
+    fn frame_at(&self, time: f32) -> Option<GhostFrame> {
+        let next = self.frames.partition_point(|frame| frame.time < time);
+        let after = *self.frames.get(next)?;
+        let Some(before) = next.checked_sub(1).map(|index| self.frames[index]) else {
+            return Some(after);
+        };
+        let t = (time - before.time) / (after.time - before.time).max(f32::EPSILON);
+        Some(GhostFrame {
+            position: before.position.lerp(after.position, t),
+            ..before
+        })
+    }
+}
+
+/// Best runs per level, read from storage once the LDTK project has loaded
+#[derive(Resource, Default)]
+pub struct Ghosts {
+    best: HashMap<String, GhostRun>,
+    /// Levels picked on the map to race against their ghost
+    pub enabled: HashSet<String>,
+}
+
+impl Ghosts {
+    pub fn best(&self, level_iid: &str) -> Option<&GhostRun> {
+        self.best.get(level_iid)
+    }
+
+    /// Keeps the run if it beats the stored one
+    fn submit(&mut self, level_iid: &str, run: GhostRun) {
+        if self
+            .best(level_iid)
+            .is_some_and(|best| best.duration() <= run.duration())
+        {
+            return;
+        }
+        let text = ron::to_string(&run).expect("ghost run should always serialize");
+        if let Err(err) = storage::write(&format!("ghost_{level_iid}"), &text) {
+            warn!("Could not store ghost for level {level_iid}: {err}");
+        }
+        info!("New best ghost for level {level_iid}");
+        self.best.insert(level_iid.to_string(), run);
+    }
+}
+
+fn read_ghost(level_iid: &str) -> Option<GhostRun> {
+    let text = storage::read(&format!("ghost_{level_iid}"))?;
+    ron::from_str(&text)
+        .map_err(|err| warn!("Ghost for level {level_iid} is corrupted: {err}"))
+        .ok()
+}
+
+/// Reads the stored ghost of every level up front, levels added by a hot reload included
+fn load_ghosts(
+    mut events: EventReader<AssetEvent<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut ghosts: ResMut<Ghosts>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        let Some(ldtk_project) = ldtk_project_assets.get(*id) else {
+            continue;
+        };
+        for level in ldtk_project.iter_raw_levels() {
+            if ghosts.best.contains_key(&level.iid) {
+                continue;
+            }
+            if let Some(run) = read_ghost(&level.iid) {
+                ghosts.best.insert(level.iid.clone(), run);
+            }
+        }
+    }
+}
+
+/// The attempt at the current level
+#[derive(Resource, Default)]
+struct GhostRecorder {
+    level_iid: Option<String>,
+    /// The level the player came in from, `None` when the attempt started in this level
+    entered_from: Option<String>,
+    run: GhostRun,
+    elapsed: f32,
+    /// Died or warped along the way
+    broken: bool,
+}
+
+#[derive(Component)]
+struct Ghost {
+    level_iid: String,
+    run: GhostRun,
+    elapsed: f32,
+}
+
+fn record_ghost_frames(
+    time: Res<Time>,
+    level_selection: Res<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    player: Query<(&Transform, &Sprite, &TextureAtlas), With<Player>>,
+    mut recorder: ResMut<GhostRecorder>,
+    mut ghosts: ResMut<Ghosts>,
+) {
+    let Some(level_iid) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+        .and_then(|ldtk_project| selected_level_iid(&level_selection, ldtk_project))
+    else {
+        return;
+    };
+    let Ok((transform, sprite, atlas)) = player.get_single() else {
+        return;
+    };
+
+    // Deaths, warps and loading a save teleport the player, which spoils the attempt
+    let position = transform.translation.truncate();
+    let teleported = recorder
+        .run
+        .frames
+        .last()
+        .is_some_and(|last| last.position.distance(position) > MAX_FRAME_STEP);
+
+    if recorder.level_iid.as_ref() == Some(&level_iid) {
+        recorder.elapsed += time.delta_seconds();
+        recorder.broken |= teleported;
+    } else {
+        // Leaving a level finishes the attempt at it, unless the player turned back
+        // and left the way they came in, which isn't a way through the level
+        let finished = std::mem::take(&mut *recorder);
+        let went_through = finished.entered_from.as_ref() != Some(&level_iid);
+        if let (Some(finished_iid), true) = (
+            &finished.level_iid,
+            went_through && !finished.broken && !teleported,
+        ) {
+            ghosts.submit(finished_iid, finished.run);
+        }
+        recorder.level_iid = Some(level_iid);
+        recorder.entered_from = finished.level_iid;
+        recorder.broken = teleported;
+    }
+
+    let frame = GhostFrame {
+        time: recorder.elapsed,
+        position,
+        index: atlas.index,
+        flip_x: sprite.flip_x,
+    };
+    recorder.run.frames.push(frame);
+}
+
+/// Starts the ghost of each picked level as the player enters it
+fn spawn_ghosts(
+    mut commands: Commands,
+    recorder: Res<GhostRecorder>,
+    ghosts: Res<Ghosts>,
+    existing: Query<(Entity, &Ghost)>,
+    player: Query<(&Transform, &Handle<Image>, &TextureAtlas), With<Player>>,
+) {
+    let Some(level_iid) = recorder.level_iid.clone() else {
+        return;
+    };
+    let enabled = ghosts.enabled.contains(&level_iid);
+    for (entity, ghost) in &existing {
+        if ghost.level_iid != level_iid || !enabled {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    // A new ghost only starts along with a new attempt
+    if !enabled || recorder.run.frames.len() != 1 {
+        return;
+    }
+    let (Some(run), Ok((transform, texture, atlas))) =
+        (ghosts.best(&level_iid).cloned(), player.get_single())
+    else {
+        return;
+    };
+
+    commands.spawn((
+        Ghost {
+            level_iid,
+            run,
+            elapsed: 0.,
+        },
+        SpriteSheetBundle {
+            sprite: Sprite {
+                color: Color::WHITE.with_a(GHOST_ALPHA),
+                ..Default::default()
+            },
+            texture: texture.clone(),
+            atlas: atlas.clone(),
+            // Just behind the player
+            transform: Transform::from_translation(transform.translation - Vec3::Z * 0.1),
+            ..Default::default()
+        },
+    ));
+}
+
+fn play_ghosts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut ghosts: Query<(
+        Entity,
+        &mut Ghost,
+        &mut Transform,
+        &mut Sprite,
+        &mut TextureAtlas,
+    )>,
+) {
+    for (entity, mut ghost, mut transform, mut sprite, mut atlas) in &mut ghosts {
+        ghost.elapsed += time.delta_seconds();
+        let Some(frame) = ghost.run.frame_at(ghost.elapsed) else {
+            // The ghost made it out of the level
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        transform.translation = frame.position.extend(transform.translation.z);
+        sprite.flip_x = frame.flip_x;
+        atlas.index = frame.index;
+    }
+}
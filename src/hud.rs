@@ -0,0 +1,272 @@
+use crate::combat::{Ammo, Health, Score};
+use crate::components::Settings;
+use crate::constants::BASE_RES;
+use crate::ldtk_spawning::item_color;
+use crate::player::{Items, Player};
+use crate::save::Playtime;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+/// Score, health, items, level name and timer overlays
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_hud).add_systems(
+            Update,
+            (
+                scale_ui,
+                score_text_update_system,
+                health_bar_update_system,
+                item_bar_update_system,
+                level_name_update_system,
+                timer_text_update_system,
+            ),
+        );
+    }
+}
+
+/// Marker to find the text entity so we can update it
+#[derive(Component)]
+struct ScoreText;
+
+/// Holds one pip per point of max health
+#[derive(Component)]
+struct HealthBar;
+
+/// Holds one icon per collected item
+#[derive(Component)]
+struct ItemBar;
+
+#[derive(Component)]
+struct LevelNameText;
+
+#[derive(Component)]
+struct TimerText;
+
+fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("Orbitron-Bold.ttf"),
+        font_size: 16.0,
+        color: Color::WHITE,
+    };
+    let panel = NodeBundle {
+        background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+        style: Style {
+            padding: UiRect::all(Val::Px(4.0)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let bar = NodeBundle {
+        style: Style {
+            column_gap: Val::Px(4.0),
+            min_height: Val::Px(12.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // top-left corner, opposite the FPS counter
+    let root = commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                left: Val::Percent(1.),
+                top: Val::Percent(1.),
+                ..panel.style.clone()
+            },
+            ..panel.clone()
+        })
+        .id();
+    let health_bar = commands.spawn((HealthBar, bar.clone())).id();
+    // sections alternate label/value, so only the odd ones need updating
+    let score_text = commands
+        .spawn((
+            ScoreText,
+            TextBundle::from_sections([
+                TextSection::new("COINS ", style.clone()),
+                TextSection::new("0", style.clone()),
+                TextSection::new("   SCORE ", style.clone()),
+                TextSection::new("0", style.clone()),
+                TextSection::new("   AMMO ", style.clone()),
+                TextSection::new("0", style.clone()),
+            ]),
+        ))
+        .id();
+    let item_bar = commands.spawn((ItemBar, bar)).id();
+    commands
+        .entity(root)
+        .push_children(&[health_bar, score_text, item_bar]);
+
+    // top-centre, the level name with the timer underneath
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                top: Val::Percent(1.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(panel.clone()).with_children(|panel| {
+                panel.spawn((LevelNameText, TextBundle::from_section("", style.clone())));
+            });
+            parent.spawn((TimerText, panel)).with_children(|panel| {
+                panel.spawn(TextBundle::from_section("", style));
+            });
+        });
+}
+
+/// Scales the UI with the window, so it looks the same at any size as it does at `BASE_RES`
+fn scale_ui(windows: Query<&Window, Changed<Window>>, mut ui_scale: ResMut<UiScale>) {
+    if let Ok(window) = windows.get_single() {
+        ui_scale.0 = (window.width() / BASE_RES.x).min(window.height() / BASE_RES.y);
+    }
+}
+
+fn score_text_update_system(
+    score: Res<Score>,
+    settings: Res<Settings>,
+    ammo: Query<Ref<Ammo>, With<Player>>,
+    mut query: Query<&mut Text, With<ScoreText>>,
+) {
+    let ammo = ammo.get_single().ok();
+    let ammo_changed = ammo.as_ref().is_some_and(DetectChanges::is_changed);
+    if !score.is_changed() && !settings.is_changed() && !ammo_changed {
+        return;
+    }
+    let language = settings.language;
+    for mut text in &mut query {
+        text.sections[0].value = format!("{} ", language.tr("COINS"));
+        text.sections[1].value = score.coins.to_string();
+        text.sections[2].value = format!("   {} ", language.tr("SCORE"));
+        text.sections[3].value = score.points.to_string();
+        text.sections[4].value = format!("   {} ", language.tr("AMMO"));
+        text.sections[5].value = ammo.as_ref().map_or(0, |ammo| ammo.0).to_string();
+    }
+}
+
+fn health_bar_update_system(
+    mut commands: Commands,
+    player: Query<&Health, (With<Player>, Changed<Health>)>,
+    bars: Query<Entity, With<HealthBar>>,
+) {
+    let Ok(health) = player.get_single() else {
+        return;
+    };
+    for bar in &bars {
+        commands
+            .entity(bar)
+            .despawn_descendants()
+            .with_children(|parent| {
+                for pip in 0..health.max {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(12.0),
+                            height: Val::Px(12.0),
+                            ..Default::default()
+                        },
+                        background_color: BackgroundColor(if pip < health.current {
+                            Color::rgb_u8(228, 59, 68)
+                        } else {
+                            Color::DARK_GRAY
+                        }),
+                        ..Default::default()
+                    });
+                }
+            });
+    }
+}
+
+fn item_bar_update_system(
+    mut commands: Commands,
+    player: Query<&Items, (With<Player>, Changed<Items>)>,
+    bars: Query<Entity, With<ItemBar>>,
+) {
+    let Ok(items) = player.get_single() else {
+        return;
+    };
+    for bar in &bars {
+        commands
+            .entity(bar)
+            .despawn_descendants()
+            .with_children(|parent| {
+                // Same colours as the keys in the world
+                for item in &items.0 {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(8.0),
+                            height: Val::Px(12.0),
+                            ..Default::default()
+                        },
+                        background_color: BackgroundColor(item_color(item)),
+                        ..Default::default()
+                    });
+                }
+            });
+    }
+}
+
+fn level_name_update_system(
+    level_selection: Res<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut query: Query<&mut Text, With<LevelNameText>>,
+) {
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    let Some(level) = ldtk_project
+        .iter_raw_levels()
+        .enumerate()
+        .find(|(index, level)| level_selection.is_match(&LevelIndices::in_root(*index), level))
+        .map(|(_, level)| level)
+    else {
+        return;
+    };
+    let name = level.identifier.replace('_', " ").to_uppercase();
+    for mut text in &mut query {
+        if text.sections[0].value != name {
+            text.sections[0].value.clone_from(&name);
+        }
+    }
+}
+
+fn timer_text_update_system(
+    settings: Res<Settings>,
+    playtime: Res<Playtime>,
+    mut timers: Query<(&mut Visibility, &Children), With<TimerText>>,
+    mut texts: Query<&mut Text>,
+) {
+    for (mut visibility, children) in &mut timers {
+        let wanted = if settings.show_timer {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+        if !settings.show_timer {
+            continue;
+        }
+        let secs = playtime.0.as_secs();
+        let millis = playtime.0.subsec_millis();
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = format!("{}:{:02}.{:03}", secs / 60, secs % 60, millis);
+        }
+    }
+}
@@ -1,13 +1,20 @@
-use crate::components::{
-    AccelerationStat, CanDie, ColliderBundle, Enemy, FakeGroundFrictionStat, IsLdtkEntity, Items,
-    JumpForceStat, MaxSpeedStat, Patrol, PatrolAnimation, Player, PlayerAnimations, SensorBundle,
+use crate::camera::CameraZone;
+use crate::combat::{Ammo, CanDie, Health, LootDrop, LootTable, Pickup};
+use crate::components::{BodyOffset, ColliderBundle, IsLdtkEntity, SensorBundle};
+use crate::constants::{self, CollideEnums};
+use crate::enemy::{Patrol, Spawner};
+use crate::level::{
+    Checkpoint, Door, EntityState, Platform, SignalKind, SignalReceiver, Trigger, TriggerKind,
+    WorldState,
 };
-use crate::constants::CollideEnums;
-use benimator::FrameRate;
+use crate::player::{Items, Player};
+use crate::stats::StatOverrides;
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 use bevy_ecs_ldtk::prelude::*;
-use bevy_ecs_ldtk::utils::ldtk_pixel_coords_to_translation_pivoted;
+use bevy_ecs_ldtk::utils::{
+    ldtk_pixel_coords_to_translation, ldtk_pixel_coords_to_translation_pivoted,
+};
 use bevy_rapier2d::prelude::*;
 
 // Spawn sensors for int-grid from LDTK
@@ -38,31 +45,106 @@ impl From<IntGridCell> for SensorBundle {
     }
 }
 
-// Spawn collider bundles for entities from LDTK
+// Spawn sensors for entities from LDTK, shaped by the same collider fields as solid bodies
+impl From<&EntityInstance> for SensorBundle {
+    fn from(entity_instance: &EntityInstance) -> SensorBundle {
+        SensorBundle {
+            collider: collider_from_fields(entity_instance),
+            sensor: Sensor,
+            rotation_constraints: LockedAxes::ROTATION_LOCKED,
+            active_events: ActiveEvents::COLLISION_EVENTS,
+        }
+    }
+}
+
+/// Fields describing an entity's body, mobs from a spawner take them from the spawner
+const BODY_FIELDS: [&str; 9] = [
+    "collider",
+    "collider_width",
+    "collider_height",
+    "offset_x",
+    "offset_y",
+    "body",
+    "friction",
+    "patrol_speed",
+    "patrol_turn_speed",
+];
+
+/// An optional float field, entities without the field at all get `None` too
+fn maybe_float_field(entity_instance: &EntityInstance, identifier: &str) -> Option<f32> {
+    entity_instance
+        .get_maybe_float_field(identifier)
+        .ok()
+        .flatten()
+        .copied()
+}
+
+/// A box the size of the entity, unless its collider fields say otherwise
+fn collider_from_fields(entity_instance: &EntityInstance) -> Collider {
+    let size = Vec2::new(
+        maybe_float_field(entity_instance, "collider_width")
+            .unwrap_or(entity_instance.width as f32),
+        maybe_float_field(entity_instance, "collider_height")
+            .unwrap_or(entity_instance.height as f32),
+    );
+    match entity_instance
+        .get_enum_field("collider")
+        .map(String::as_str)
+    {
+        // Upright, as wide as the collider and as tall including the rounded ends
+        Ok("Capsule") => Collider::capsule_y(((size.y - size.x) / 2.).max(0.), size.x / 2.),
+        Ok("Ball") => Collider::ball(size.x / 2.),
+        _ => Collider::cuboid(size.x / 2., size.y / 2.),
+    }
+}
+
+fn body_offset(entity_instance: &EntityInstance) -> Vec2 {
+    Vec2::new(
+        maybe_float_field(entity_instance, "offset_x").unwrap_or_default(),
+        maybe_float_field(entity_instance, "offset_y").unwrap_or_default(),
+    )
+}
+
+// Spawn collider bundles for entities from LDTK, a fixed body unless its fields say otherwise
 impl From<&EntityInstance> for ColliderBundle {
     fn from(entity_instance: &EntityInstance) -> ColliderBundle {
-        let rotation_constraints = LockedAxes::ROTATION_LOCKED;
-
-        match entity_instance.identifier.as_ref() {
-            "Player" => ColliderBundle {
-                collider: Collider::capsule_y(2., 6.),
-                rigid_body: RigidBody::Dynamic,
-                rotation_constraints,
-                active_events: ActiveEvents::COLLISION_EVENTS,
-                friction: Friction {
-                    coefficient: 0.0,
-                    combine_rule: CoefficientCombineRule::Min,
-                },
-                ..Default::default()
+        let rigid_body = match entity_instance.get_enum_field("body").map(String::as_str) {
+            Ok("Dynamic") => RigidBody::Dynamic,
+            Ok("Kinematic") => RigidBody::KinematicVelocityBased,
+            _ => RigidBody::Fixed,
+        };
+        let friction = maybe_float_field(entity_instance, "friction").map_or_else(
+            Friction::default,
+            |coefficient| Friction {
+                coefficient,
+                // The less grippy surface wins, so 0 slides along anything
+                combine_rule: CoefficientCombineRule::Min,
             },
-            "Snake" => ColliderBundle {
-                collider: Collider::cuboid(4., 4.),
-                rigid_body: RigidBody::KinematicVelocityBased,
-                rotation_constraints,
-                active_events: ActiveEvents::COLLISION_EVENTS,
-                ..Default::default()
-            },
-            _ => ColliderBundle::default(),
+        );
+
+        ColliderBundle {
+            collider: collider_from_fields(entity_instance),
+            rigid_body,
+            rotation_constraints: LockedAxes::ROTATION_LOCKED,
+            active_events: ActiveEvents::COLLISION_EVENTS,
+            friction,
+            offset: BodyOffset(body_offset(entity_instance)),
+            ..Default::default()
+        }
+    }
+}
+
+// Stats set on the LDTK entity itself
+impl From<&EntityInstance> for StatOverrides {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        StatOverrides {
+            acceleration: maybe_float_field(entity_instance, "acceleration"),
+            max_speed_x: maybe_float_field(entity_instance, "max_speed_x"),
+            max_speed_y: maybe_float_field(entity_instance, "max_speed_y"),
+            jump_force: maybe_float_field(entity_instance, "jump_force"),
+            ground_friction: maybe_float_field(entity_instance, "ground_friction"),
+            patrol_speed: maybe_float_field(entity_instance, "patrol_speed"),
+            patrol_turn_speed: maybe_float_field(entity_instance, "patrol_turn_speed"),
         }
     }
 }
@@ -80,6 +162,106 @@ impl From<&EntityInstance> for Items {
     }
 }
 
+// Spawn pickup component for entities from LDTK
+impl From<&EntityInstance> for Pickup {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        match entity_instance.identifier.as_ref() {
+            "Coin" => Pickup::Coin(
+                entity_instance
+                    .get_int_field("value")
+                    .ok()
+                    .and_then(|value| u32::try_from(*value).ok())
+                    .unwrap_or(1),
+            ),
+            "Key" => Pickup::Item(
+                entity_instance
+                    .get_enum_field("item")
+                    .expect("item field should be correctly typed")
+                    .clone(),
+            ),
+            _ => Pickup::default(),
+        }
+    }
+}
+
+// Spawn door component for entities from LDTK
+impl From<&EntityInstance> for Door {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Door {
+            key: entity_instance
+                .get_maybe_enum_field("key")
+                .expect("key field should be correctly typed")
+                .clone(),
+            open: false,
+        }
+    }
+}
+
+// Spawn trigger component for entities from LDTK
+impl From<&EntityInstance> for Trigger {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let kind = match entity_instance.identifier.as_ref() {
+            "PressurePlate" => TriggerKind::PressurePlate,
+            "Trigger" => TriggerKind::Region,
+            _ => TriggerKind::Lever,
+        };
+        let mode = match entity_instance
+            .get_enum_field("mode")
+            .expect("mode field should be correctly typed")
+            .as_ref()
+        {
+            "Off" => SignalKind::Off,
+            "Toggle" => SignalKind::Toggle,
+            _ => SignalKind::On,
+        };
+        Trigger {
+            kind,
+            mode,
+            targets: entity_instance
+                .iter_entity_refs_field("targets")
+                .expect("targets field should be correctly typed")
+                .map(|entity_ref| entity_ref.entity_iid.clone())
+                .collect(),
+            once: *entity_instance
+                .get_bool_field("once")
+                .expect("once field should be correctly typed"),
+            ..Default::default()
+        }
+    }
+}
+
+/// Loot fields, as the prefix of each pickup's `_chance` and `_rolls` fields
+const LOOT_FIELDS: [(&str, Pickup); 3] = [
+    ("coin", Pickup::Coin(1)),
+    ("health", Pickup::Health(1)),
+    ("ammo", Pickup::Ammo(2)),
+];
+
+// What an enemy drops when killed, from its loot fields. A pickup without a chance never drops,
+// and one that drops comes out between one and its rolls times
+impl From<&EntityInstance> for LootTable {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        LootTable(
+            LOOT_FIELDS
+                .into_iter()
+                .filter_map(|(prefix, pickup)| {
+                    let chance = maybe_float_field(entity_instance, &format!("{prefix}_chance"))?;
+                    let rolls = entity_instance
+                        .get_int_field(&format!("{prefix}_rolls"))
+                        .ok()
+                        .and_then(|rolls| u32::try_from(*rolls).ok())
+                        .unwrap_or(1);
+                    Some(LootDrop {
+                        pickup,
+                        chance,
+                        rolls: 1..=rolls,
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
 impl LdtkEntity for Patrol {
     fn bundle_entity(
         entity_instance: &EntityInstance,
@@ -89,75 +271,225 @@ impl LdtkEntity for Patrol {
         _: &AssetServer,
         _: &mut Assets<TextureAtlasLayout>,
     ) -> Patrol {
-        let mut points = Vec::new();
+        Patrol {
+            points: patrol_points(entity_instance, layer_instance),
+            index: 1,
+            forward: true,
+        }
+    }
+}
+
+/// The entity's own position followed by each point in its "patrol" field
+fn patrol_points(entity_instance: &EntityInstance, layer_instance: &LayerInstance) -> Vec<Vec2> {
+    let mut points = Vec::new();
+    points.push(
+        ldtk_pixel_coords_to_translation_pivoted(
+            entity_instance.px,
+            layer_instance.c_hei * layer_instance.grid_size,
+            IVec2::new(entity_instance.width, entity_instance.height),
+            entity_instance.pivot,
+        ) + body_offset(entity_instance),
+    );
+
+    let ldtk_patrol_points = entity_instance
+        .iter_points_field("patrol")
+        .expect("patrol field should be correclty typed");
+
+    for ldtk_point in ldtk_patrol_points {
+        // The +1 is necessary here due to the pivot of the entities in the sample
+        // file.
+        // The patrols set up in the file look flat and grounded,
+        // but technically they're not if you consider the pivot,
+        // which is at the bottom-center for the skulls.
+        let pixel_coords = (ldtk_point.as_vec2() + Vec2::new(0.5, 0.5))
+            * Vec2::splat(layer_instance.grid_size as f32);
+
         points.push(
             ldtk_pixel_coords_to_translation_pivoted(
-                entity_instance.px,
+                pixel_coords.as_ivec2(),
                 layer_instance.c_hei * layer_instance.grid_size,
                 IVec2::new(entity_instance.width, entity_instance.height),
                 entity_instance.pivot,
-            ) - Vec2 { x: 0.0, y: 8.0 },
+            ) + body_offset(entity_instance),
         );
+    }
 
-        let ldtk_patrol_points = entity_instance
-            .iter_points_field("patrol")
-            .expect("patrol field should be correclty typed");
+    points
+}
 
-        for ldtk_point in ldtk_patrol_points {
-            // The +1 is necessary here due to the pivot of the entities in the sample
-            // file.
-            // The patrols set up in the file look flat and grounded,
-            // but technically they're not if you consider the pivot,
-            // which is at the bottom-center for the skulls.
-            let pixel_coords = (ldtk_point.as_vec2() + Vec2::new(0.5, 0.5))
-                * Vec2::splat(layer_instance.grid_size as f32);
+impl LdtkEntity for Spawner {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _: Option<&Handle<Image>>,
+        _: Option<&TilesetDefinition>,
+        _: &AssetServer,
+        _: &mut Assets<TextureAtlasLayout>,
+    ) -> Spawner {
+        let mob = EntityInstance {
+            identifier: entity_instance
+                .get_enum_field("mob")
+                .expect("mob field should be correctly typed")
+                .clone(),
+            width: entity_instance
+                .get_int_field("mob_width")
+                .copied()
+                .unwrap_or(32),
+            height: entity_instance
+                .get_int_field("mob_height")
+                .copied()
+                .unwrap_or(32),
+            field_instances: entity_instance
+                .field_instances
+                .iter()
+                .filter(|field| {
+                    BODY_FIELDS.contains(&field.identifier.as_str())
+                        || LOOT_FIELDS
+                            .iter()
+                            .any(|(prefix, _)| field.identifier.starts_with(&format!("{prefix}_")))
+                })
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        let interval = *entity_instance
+            .get_float_field("interval")
+            .expect("interval field should be correctly typed");
+
+        Spawner {
+            mob,
+            patrol: patrol_points(entity_instance, layer_instance),
+            timer: Timer::from_seconds(interval, TimerMode::Repeating),
+            max_alive: entity_instance
+                .get_int_field("max_alive")
+                .ok()
+                .and_then(|max_alive| usize::try_from(*max_alive).ok())
+                .unwrap_or(1),
+            total: entity_instance
+                .get_maybe_int_field("total")
+                .expect("total field should be correctly typed")
+                .and_then(|total| u32::try_from(total).ok()),
+            spawned: 0,
+            on_trigger: *entity_instance
+                .get_bool_field("on_trigger")
+                .expect("on_trigger field should be correctly typed"),
+            targets: entity_instance
+                .iter_entity_refs_field("targets")
+                .expect("targets field should be correctly typed")
+                .map(|entity_ref| entity_ref.entity_iid.clone())
+                .collect(),
+            cleared: false,
+        }
+    }
+}
+
+impl LdtkEntity for Platform {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _: Option<&Handle<Image>>,
+        _: Option<&TilesetDefinition>,
+        _: &AssetServer,
+        _: &mut Assets<TextureAtlasLayout>,
+    ) -> Platform {
+        let size = IVec2::new(entity_instance.width, entity_instance.height);
+        let layer_height = layer_instance.c_hei * layer_instance.grid_size;
+        let start = ldtk_pixel_coords_to_translation_pivoted(
+            entity_instance.px,
+            layer_height,
+            size,
+            entity_instance.pivot,
+        );
 
-            points.push(
+        // Without a destination the platform just stays where it was placed
+        let end = entity_instance
+            .get_maybe_point_field("destination")
+            .expect("destination field should be correctly typed")
+            .map_or(start, |ldtk_point| {
+                let pixel_coords = (ldtk_point.as_vec2() + Vec2::new(0.5, 0.5))
+                    * Vec2::splat(layer_instance.grid_size as f32);
                 ldtk_pixel_coords_to_translation_pivoted(
                     pixel_coords.as_ivec2(),
-                    layer_instance.c_hei * layer_instance.grid_size,
-                    IVec2::new(entity_instance.width, entity_instance.height),
+                    layer_height,
+                    size,
                     entity_instance.pivot,
-                ) - Vec2 { x: 0.0, y: 8.0 },
-            );
+                )
+            });
+
+        Platform {
+            start,
+            end,
+            speed: *entity_instance
+                .get_float_field("speed")
+                .expect("speed field should be correctly typed"),
         }
+    }
+}
 
-        Patrol {
-            points,
-            index: 1,
-            forward: true,
+impl LdtkEntity for CameraZone {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _: Option<&Handle<Image>>,
+        _: Option<&TilesetDefinition>,
+        _: &AssetServer,
+        _: &mut Assets<TextureAtlasLayout>,
+    ) -> CameraZone {
+        let size = IVec2::new(entity_instance.width, entity_instance.height);
+        let layer_height = layer_instance.c_hei * layer_instance.grid_size;
+        let center = ldtk_pixel_coords_to_translation_pivoted(
+            entity_instance.px,
+            layer_height,
+            size,
+            entity_instance.pivot,
+        );
+
+        let pin = entity_instance
+            .get_maybe_point_field("pin")
+            .expect("pin field should be correctly typed")
+            .map(|ldtk_point| {
+                let pixel_coords = (ldtk_point.as_vec2() + Vec2::new(0.5, 0.5))
+                    * Vec2::splat(layer_instance.grid_size as f32);
+                ldtk_pixel_coords_to_translation(pixel_coords.as_ivec2(), layer_height) - center
+            });
+
+        CameraZone {
+            size: size.as_vec2(),
+            zoom: *entity_instance
+                .get_float_field("zoom")
+                .expect("zoom field should be correctly typed"),
+            lock_x: *entity_instance
+                .get_bool_field("lock_x")
+                .expect("lock_x field should be correctly typed"),
+            lock_y: *entity_instance
+                .get_bool_field("lock_y")
+                .expect("lock_y field should be correctly typed"),
+            pin,
+            priority: *entity_instance
+                .get_int_field("priority")
+                .expect("priority field should be correctly typed"),
+            blend: *entity_instance
+                .get_float_field("blend")
+                .expect("blend field should be correctly typed"),
         }
     }
 }
 
 pub fn setup_player_components(mut cmd: Commands, query: Query<Entity, Added<Player>>) {
-    let o = 22; // animation index offset in the sprite sheet
-    let player_animations = PlayerAnimations {
-        idle: benimator::Animation::from_indices((o + 12)..=(o + 13), FrameRate::from_fps(1.5)),
-        walk: benimator::Animation::from_indices((o + 1)..=(o + 4), FrameRate::from_fps(12.0)),
-        jump_prep: benimator::Animation::from_indices((o + 5)..=(o + 5), FrameRate::from_fps(12.0)),
-        jump_up: benimator::Animation::from_indices((o + 6)..=(o + 6), FrameRate::from_fps(12.0)),
-        jump_down: benimator::Animation::from_indices((o + 7)..=(o + 7), FrameRate::from_fps(12.0)),
-        jump_land: benimator::Animation::from_indices((o + 8)..=(o + 8), FrameRate::from_fps(12.0)),
-        hit: benimator::Animation::from_indices((o + 9)..(o + 10), FrameRate::from_fps(12.0)),
-        slash: benimator::Animation::from_indices(
-            [(o + 12), (o + 11), (o + 12), (o + 13)],
-            FrameRate::from_fps(12.0),
-        ),
-        punch: benimator::Animation::from_indices([(o + 14), (o + 12)], FrameRate::from_fps(12.0)),
-        run: benimator::Animation::from_indices((o + 15)..=(o + 18), FrameRate::from_fps(12.0)),
-        climb: benimator::Animation::from_indices((o + 19)..=(o + 22), FrameRate::from_fps(12.0)),
-    };
-
     if let Ok(entity) = query.get_single() {
         if let Some(mut entity_command) = cmd.get_entity(entity) {
             entity_command
-                .insert(player_animations)
                 .insert(ActiveCollisionTypes::all())
-                .insert(AccelerationStat(15.0))
-                .insert(MaxSpeedStat(Vec2 { x: 100.0, y: 400.0 }))
-                .insert(JumpForceStat(400.0))
-                .insert(FakeGroundFrictionStat(-0.1))
+                .insert(Health {
+                    current: constants::PLAYER_MAX_HEALTH,
+                    max: constants::PLAYER_MAX_HEALTH,
+                })
+                .insert(Ammo(0))
+                // Pickups still report collisions, but never push the player around
+                .insert(SolverGroups::new(
+                    constants::PLAYER_GROUP,
+                    !constants::PICKUP_GROUP,
+                ))
                 .insert(CanDie {
                     is_dead: false,
                     dead_animation_timer: Timer::from_seconds(1.5, TimerMode::Once),
@@ -166,18 +498,118 @@ pub fn setup_player_components(mut cmd: Commands, query: Query<Entity, Added<Pla
     }
 }
 
-pub fn add_patrol_animation_enemy(
+pub fn add_pickup_sprite(mut query: Query<(&Pickup, &mut Sprite), Added<Pickup>>) {
+    for (pickup, mut sprite) in &mut query {
+        let (color, size) = match pickup {
+            Pickup::Coin(_) => (Color::GOLD, 6.),
+            Pickup::Health(_) => (Color::CRIMSON, 7.),
+            Pickup::Ammo(_) => (Color::SILVER, 5.),
+            Pickup::Item(item) => (item_color(item), 8.),
+        };
+        sprite.color = color;
+        sprite.custom_size = Some(Vec2::splat(size));
+    }
+}
+
+pub fn add_checkpoint_sprite(mut query: Query<&mut Sprite, Added<Checkpoint>>) {
+    for mut sprite in &mut query {
+        sprite.color = Color::rgb_u8(62, 137, 72);
+        sprite.custom_size = Some(Vec2::new(4., 32.));
+    }
+}
+
+pub fn add_door_sprite(mut query: Query<(&Door, &EntityInstance, &mut Sprite), Added<Door>>) {
+    for (door, entity_instance, mut sprite) in &mut query {
+        sprite.color = door.key.as_deref().map_or(Color::GRAY, item_color);
+        sprite.custom_size = Some(Vec2::new(
+            entity_instance.width as f32,
+            entity_instance.height as f32,
+        ));
+    }
+}
+
+pub fn add_trigger_sprite(
+    mut query: Query<(&Trigger, &EntityInstance, &mut Sprite, &mut Visibility), Added<Trigger>>,
+) {
+    for (trigger, entity_instance, mut sprite, mut visibility) in &mut query {
+        sprite.color = if trigger.active {
+            Color::WHITE
+        } else {
+            Color::GRAY
+        };
+        sprite.custom_size = Some(Vec2::new(
+            entity_instance.width as f32,
+            entity_instance.height as f32,
+        ));
+        if trigger.kind == TriggerKind::Region {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+pub fn add_platform_sprite(mut query: Query<(&EntityInstance, &mut Sprite), Added<Platform>>) {
+    for (entity_instance, mut sprite) in &mut query {
+        sprite.color = Color::rgb_u8(90, 105, 136);
+        sprite.custom_size = Some(Vec2::new(
+            entity_instance.width as f32,
+            entity_instance.height as f32,
+        ));
+    }
+}
+
+/// Keys and the doors they open share a colour
+pub fn item_color(item: &str) -> Color {
+    match item {
+        "RedKey" => Color::rgb_u8(228, 59, 68),
+        "BlueKey" => Color::rgb_u8(0, 149, 233),
+        "GreenKey" => Color::rgb_u8(99, 199, 77),
+        _ => Color::TEAL,
+    }
+}
+
+/// Re-applies recorded `WorldState` to LDTK entities as their level respawns
+pub fn apply_world_state(
     mut cmd: Commands,
-    query: Query<Entity, (Added<Enemy>, Added<Patrol>)>,
+    world_state: Res<WorldState>,
+    mut query: Query<
+        (
+            Entity,
+            &EntityInstance,
+            Option<&mut Trigger>,
+            Option<&mut SignalReceiver>,
+            Option<&mut Spawner>,
+        ),
+        Added<EntityInstance>,
+    >,
 ) {
-    let o = 68;
-    let partrol_animation = PatrolAnimation(benimator::Animation::from_indices(
-        (o + 1)..=(o + 4),
-        FrameRate::from_fps(4.0),
-    ));
-    for entity in &query {
-        if let Some(mut entity_command) = cmd.get_entity(entity) {
-            entity_command.insert(partrol_animation.clone());
+    for (entity, entity_instance, trigger, receiver, spawner) in &mut query {
+        let Some(state) = world_state.0.get(&entity_instance.iid) else {
+            continue;
+        };
+        match (*state, trigger, receiver, spawner) {
+            (EntityState::Killed | EntityState::Collected, ..) => {
+                cmd.entity(entity).despawn_recursive();
+            }
+            (EntityState::Triggered { active, fired }, Some(mut trigger), ..) => {
+                trigger.active = active;
+                trigger.fired = fired;
+            }
+            (EntityState::Powered(powered), _, Some(mut receiver), _) => {
+                receiver.powered = powered;
+            }
+            (
+                EntityState::Spawning { spawned, powered },
+                _,
+                Some(mut receiver),
+                Some(mut spawner),
+            ) => {
+                receiver.powered = powered;
+                spawner.spawned = spawned;
+            }
+            (EntityState::Cleared, _, _, Some(mut spawner)) => {
+                spawner.cleared = true;
+            }
+            _ => (),
         }
     }
 }
@@ -188,12 +620,8 @@ pub fn fix_sprite_translation(mut query: Query<&mut Sprite, Added<IsLdtkEntity>>
     }
 }
 
-pub fn fix_enemy_hitbox(mut query: Query<&mut Transform, Added<Enemy>>) {
-    for mut transform in &mut query {
-        transform.translation -= Vec3 {
-            x: 0.0,
-            y: 8.0,
-            z: 0.0,
-        }
+pub fn apply_body_offset(mut query: Query<(&mut Transform, &BodyOffset), Added<BodyOffset>>) {
+    for (mut transform, offset) in &mut query {
+        transform.translation += offset.0.extend(0.);
     }
 }
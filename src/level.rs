@@ -0,0 +1,692 @@
+use crate::components::{ColliderBundle, SensorBundle, Settings};
+use crate::enemy::{SpawnedBy, Spawner};
+use crate::player::{Climbable, Items, Player};
+use crate::save::SaveRequest;
+use crate::{constants, ldtk_spawning, GameSet};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// The LDTK world: walls, ladders, water, doors, triggers, platforms, checkpoints,
+/// and the state that outlives levels being unloaded
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldState>()
+            .init_resource::<RespawnPoint>()
+            .init_resource::<VisitedLevels>()
+            .add_event::<Signal>()
+            .insert_resource(LevelSelection::index(0))
+            .insert_resource(LdtkSettings {
+                level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
+                    load_level_neighbors: true,
+                },
+                set_clear_color: SetClearColor::FromLevelBackground,
+                ..Default::default()
+            })
+            // - Register entities
+            .register_ldtk_entity::<DoorBundle>("Door")
+            .register_ldtk_entity::<TriggerBundle>("Lever")
+            .register_ldtk_entity::<TriggerBundle>("PressurePlate")
+            .register_ldtk_entity::<TriggerBundle>("Trigger")
+            .register_ldtk_entity::<PlatformBundle>("Platform")
+            .register_ldtk_entity::<CheckpointBundle>("Checkpoint")
+            // - Register "collide" int grids
+            .register_ldtk_int_cell_for_layer::<WallBundle>(
+                "Collide",
+                constants::CollideEnums::RedBrick as i32,
+            )
+            .register_ldtk_int_cell_for_layer::<WallBundle>(
+                "Collide",
+                constants::CollideEnums::BlueBrick as i32,
+            )
+            .register_ldtk_int_cell_for_layer::<LadderBundle>(
+                "Collide",
+                constants::CollideEnums::Ladder as i32,
+            )
+            .register_ldtk_int_cell_for_layer::<WaterBundle>(
+                "Collide",
+                constants::CollideEnums::Water as i32,
+            )
+            .add_systems(Startup, setup)
+            // - Delayed startup systems (Due to the way LDTK loads stuff in)
+            .add_systems(
+                Update,
+                (
+                    ldtk_spawning::fix_sprite_translation,
+                    ldtk_spawning::apply_body_offset,
+                    ldtk_spawning::add_door_sprite,
+                    ldtk_spawning::apply_world_state.before(ldtk_spawning::add_trigger_sprite),
+                    ldtk_spawning::add_trigger_sprite,
+                    ldtk_spawning::add_platform_sprite,
+                    ldtk_spawning::add_checkpoint_sprite,
+                ),
+            )
+            .add_systems(FixedUpdate, move_platforms.in_set(GameSet::Movement))
+            .add_systems(
+                Update,
+                (
+                    check_touched_checkpoint.in_set(GameSet::Sensors),
+                    (spawn_wall_collision, update_level_selection).in_set(GameSet::Movement),
+                    // - Level interaction, a trigger opens its door on the same frame
+                    (
+                        update_trigger_intersections.in_set(GameSet::Sensors),
+                        (fire_triggers, receive_signals, update_doors)
+                            .chain()
+                            .in_set(GameSet::Combat),
+                    )
+                        .chain(),
+                    // Freshly spawned entities count as changed, so restore them first
+                    record_world_state.after(ldtk_spawning::apply_world_state),
+                ),
+            );
+    }
+}
+
+#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
+pub struct WallBundle {
+    wall: Wall,
+}
+
+#[derive(Clone, Default, Bundle, LdtkIntCell)]
+pub struct LadderBundle {
+    #[from_int_grid_cell]
+    pub sensor_bundle: SensorBundle,
+    pub climbable: Climbable,
+}
+
+#[derive(Clone, Default, Bundle, LdtkIntCell)]
+pub struct WaterBundle {
+    #[from_int_grid_cell]
+    pub sensor_bundle: SensorBundle,
+    pub water: Water,
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct CheckpointBundle {
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub sensor_bundle: SensorBundle,
+    pub checkpoint: Checkpoint,
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct DoorBundle {
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    #[from_entity_instance]
+    pub door: Door,
+    pub signal_receiver: SignalReceiver,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct TriggerBundle {
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub sensor_bundle: SensorBundle,
+    #[from_entity_instance]
+    pub trigger: Trigger,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct PlatformBundle {
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    #[ldtk_entity]
+    pub platform: Platform,
+    pub signal_receiver: SignalReceiver,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Water;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Wall;
+
+/// What happened to an LDTK entity that should survive its level unloading
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EntityState {
+    Killed,
+    Collected,
+    /// Lever or plate position, and whether a `once` trigger already fired
+    Triggered {
+        active: bool,
+        fired: bool,
+    },
+    Powered(bool),
+    /// A spawner part way through its wave, mobs still alive are sent again
+    Spawning {
+        spawned: u32,
+        powered: bool,
+    },
+    Cleared,
+}
+
+/// Entity states keyed by LDTK entity iid, re-applied whenever a level respawns
+#[derive(Resource, Default)]
+pub struct WorldState(pub HashMap<String, EntityState>);
+
+impl WorldState {
+    /// Entities spawned at runtime have no iid and are never recorded
+    pub fn record(&mut self, entity_instance: Option<&EntityInstance>, state: EntityState) {
+        if let Some(entity_instance) = entity_instance.filter(|e| !e.iid.is_empty()) {
+            self.0.insert(entity_instance.iid.clone(), state);
+        }
+    }
+}
+
+/// Iids of every level the player has been in
+#[derive(Resource, Default)]
+pub struct VisitedLevels(pub HashSet<String>);
+
+/// Where the player comes back after dying, moved by touching a `Checkpoint`
+#[derive(Resource, Clone, Debug)]
+pub struct RespawnPoint {
+    pub level_iid: Option<String>,
+    pub position: Vec3,
+}
+
+impl Default for RespawnPoint {
+    fn default() -> Self {
+        RespawnPoint {
+            level_iid: None,
+            position: crate::constants::DEFAULT_SPAWN,
+        }
+    }
+}
+
+/// Solid until the player holds `key` in their `Items`, or it is powered by a signal
+#[derive(Clone, PartialEq, Eq, Debug, Default, Component)]
+pub struct Door {
+    pub key: Option<String>,
+    pub open: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum SignalKind {
+    #[default]
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum TriggerKind {
+    /// Flipped by the player pressing the interact key while touching it
+    #[default]
+    Lever,
+    /// Held down while the player stands on it
+    PressurePlate,
+    /// Fires when the player walks into it
+    Region,
+}
+
+/// Sends `mode` to every entity in `targets` (LDTK entity iids) when it activates
+#[derive(Clone, PartialEq, Debug, Default, Component)]
+pub struct Trigger {
+    pub kind: TriggerKind,
+    pub mode: SignalKind,
+    pub targets: Vec<String>,
+    pub once: bool,
+    pub fired: bool,
+    pub active: bool,
+    pub player_inside: bool,
+}
+
+/// Anything a `Trigger` can point at
+#[derive(Clone, PartialEq, Eq, Debug, Default, Component)]
+pub struct SignalReceiver {
+    pub powered: bool,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Checkpoint;
+
+#[derive(Clone, PartialEq, Debug, Default, Component)]
+pub struct Platform {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub speed: f32,
+}
+
+#[derive(Event)]
+pub struct Signal {
+    /// Iid of the LDTK entity receiving the signal
+    pub target: String,
+    pub kind: SignalKind,
+}
+
+pub fn setup(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    cmd.spawn(LdtkWorldBundle {
+        ldtk_handle: asset_server.load("world.ldtk"),
+        ..Default::default()
+    });
+}
+
+/// Spawns heron collisions for the walls of a level
+///
+/// You could just insert a `ColliderBundle` in to the `WallBundle`,
+/// but this spawns a different collider for EVERY wall tile.
+/// This approach leads to bad performance.
+///
+/// Instead, by flagging the wall tiles and spawning the collisions later,
+/// we can minimize the amount of colliding entities.
+///
+/// The algorithm used here is a nice compromise between simplicity, speed,
+/// and a small number of rectangle colliders.
+/// In basic terms, it will:
+/// 1. consider where the walls are
+/// 2. combine wall tiles into flat "plates" in each individual row
+/// 3. combine the plates into rectangles across multiple rows wherever possible
+/// 4. spawn colliders for each rectangle
+/// # Panics
+#[allow(clippy::too_many_lines)]
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_possible_wrap)]
+pub fn spawn_wall_collision(
+    mut commands: Commands,
+    wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
+    parent_query: Query<&Parent, Without<Wall>>,
+    level_query: Query<(Entity, &LevelIid)>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    level_assets: Res<Assets<LdtkExternalLevel>>,
+) {
+    /// Represents a wide wall that is 1 tile tall
+    /// Used to spawn wall collisions
+    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+    struct Plate {
+        left: i32,
+        right: i32,
+    }
+
+    /// A simple rectangle type representing a wall of any size
+    struct Rect {
+        left: i32,
+        right: i32,
+        top: i32,
+        bottom: i32,
+    }
+
+    // Consider where the walls are
+    // storing them as GridCoords in a HashSet for quick, easy lookup
+    //
+    // The key of this map will be the entity of the level the wall belongs to.
+    // This has two consequences in the resulting collision entities:
+    // 1. it forces the walls to be split along level boundaries
+    // 2. it lets us easily add the collision entities as children of the appropriate level entity
+    let mut level_to_wall_locations: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
+
+    wall_query.iter().for_each(|(&grid_coords, parent)| {
+        // An intgrid tile's direct parent will be a layer entity, not the level entity
+        // To get the level entity, you need the tile's grandparent.
+        // This is where parent_query comes in.
+        if let Ok(grandparent) = parent_query.get(parent.get()) {
+            level_to_wall_locations
+                .entry(grandparent.get())
+                .or_default()
+                .insert(grid_coords);
+        }
+    });
+
+    if !wall_query.is_empty() {
+        level_query.iter().for_each(|(level_entity, level_iid)| {
+            if let Some(level_walls) = level_to_wall_locations.get(&level_entity) {
+                let ldtk_project = ldtk_project_assets
+                    .get(ldtk_projects.single())
+                    .expect("Project should be loaded if level has spawned");
+
+                let level = ldtk_project
+                    .as_parent()
+                    .get_external_level_by_iid(&level_assets, &level_iid.to_string())
+                    .expect("Spawned level should exist in LDtk project");
+
+                let LayerInstance {
+                    c_wid: width,
+                    c_hei: height,
+                    grid_size,
+                    ..
+                } = level.layer_instances()[0];
+
+                // combine wall tiles into flat "plates" in each individual row
+                let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+                for y in 0..height {
+                    let mut row_plates: Vec<Plate> = Vec::new();
+                    let mut plate_start = None;
+
+                    // + 1 to the width so the algorithm "terminates" plates that touch the right edge
+                    for x in 0..=width {
+                        match (plate_start, level_walls.contains(&GridCoords { x, y })) {
+                            (Some(s), false) => {
+                                row_plates.push(Plate {
+                                    left: s,
+                                    right: x - 1,
+                                });
+                                plate_start = None;
+                            }
+                            (None, true) => plate_start = Some(x),
+                            _ => (),
+                        }
+                    }
+
+                    plate_stack.push(row_plates);
+                }
+
+                // combine "plates" into rectangles across multiple rows
+                let mut rect_builder: HashMap<Plate, Rect> = HashMap::new();
+                let mut prev_row: Vec<Plate> = Vec::new();
+                let mut wall_rects: Vec<Rect> = Vec::new();
+
+                // an extra empty row so the algorithm "finishes" the rects that touch the top edge
+                plate_stack.push(Vec::new());
+
+                for (y, current_row) in plate_stack.into_iter().enumerate() {
+                    for prev_plate in &prev_row {
+                        if !current_row.contains(prev_plate) {
+                            // remove the finished rect so that the same plate in the future starts a new rect
+                            if let Some(rect) = rect_builder.remove(prev_plate) {
+                                wall_rects.push(rect);
+                            }
+                        }
+                    }
+                    for plate in &current_row {
+                        rect_builder
+                            .entry(plate.clone())
+                            .and_modify(|e| e.top += 1)
+                            .or_insert(Rect {
+                                bottom: y as i32,
+                                top: y as i32,
+                                left: plate.left,
+                                right: plate.right,
+                            });
+                    }
+                    prev_row = current_row;
+                }
+
+                commands.entity(level_entity).with_children(|level| {
+                    // Spawn colliders for every rectangle..
+                    // Making the collider a child of the level serves two purposes:
+                    // 1. Adjusts the transforms to be relative to the level for free
+                    // 2. the colliders will be despawned automatically when levels unload
+                    for wall_rect in wall_rects {
+                        level
+                            .spawn_empty()
+                            .insert(Collider::cuboid(
+                                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
+                                    * grid_size as f32
+                                    / 2.,
+                                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
+                                    * grid_size as f32
+                                    / 2.,
+                            ))
+                            .insert(RigidBody::Fixed)
+                            .insert(Transform::from_xyz(
+                                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
+                                    / 2.,
+                                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
+                                    / 2.,
+                                0.,
+                            ))
+                            .insert(GlobalTransform::default())
+                            .insert(ActiveEvents::COLLISION_EVENTS);
+                    }
+                });
+            }
+        });
+    }
+}
+
+pub fn update_level_selection(
+    level_query: Query<(&LevelIid, &Transform), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut level_selection: ResMut<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    for (level_iid, level_transform) in &level_query {
+        let ldtk_project = ldtk_project_assets
+            .get(ldtk_projects.single())
+            .expect("Project should be loaded if level has spawned");
+
+        let level = ldtk_project
+            .get_raw_level_by_iid(&level_iid.to_string())
+            .expect("Spawned level should exist in LDtk project");
+
+        let level_bounds = Rect {
+            min: Vec2::new(level_transform.translation.x, level_transform.translation.y),
+            max: Vec2::new(
+                level_transform.translation.x + level.px_wid as f32,
+                level_transform.translation.y + level.px_hei as f32,
+            ),
+        };
+        for player_transform in &player_query {
+            if player_transform.translation.x < level_bounds.max.x
+                && player_transform.translation.x > level_bounds.min.x
+                && player_transform.translation.y < level_bounds.max.y
+                && player_transform.translation.y > level_bounds.min.y
+            {
+                *level_selection = LevelSelection::iid(level.iid.clone());
+            }
+        }
+    }
+}
+
+pub fn check_touched_checkpoint(
+    player: Query<Entity, With<Player>>,
+    checkpoints: Query<&GlobalTransform, With<Checkpoint>>,
+    level_selection: Res<LevelSelection>,
+    mut respawn_point: ResMut<RespawnPoint>,
+    mut save_requests: EventWriter<SaveRequest>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.read() {
+        if let CollisionEvent::Started(collider_a, collider_b, _) = collision {
+            let checkpoint = if player.contains(*collider_a) {
+                checkpoints.get(*collider_b)
+            } else if player.contains(*collider_b) {
+                checkpoints.get(*collider_a)
+            } else {
+                continue;
+            };
+            let Ok(checkpoint) = checkpoint else {
+                continue;
+            };
+            let position = checkpoint
+                .translation()
+                .truncate()
+                .extend(constants::DEFAULT_SPAWN.z);
+            if respawn_point.position == position {
+                continue;
+            }
+            respawn_point.position = position;
+            if let LevelSelection::Iid(level_iid) = level_selection.as_ref() {
+                respawn_point.level_iid = Some(level_iid.to_string());
+            }
+            // Autosave
+            save_requests.send(SaveRequest);
+        }
+    }
+}
+
+pub fn update_trigger_intersections(
+    mut triggers: Query<&mut Trigger>,
+    player: Query<Entity, With<Player>>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.read() {
+        let (collider_a, collider_b, inside) = match collision {
+            CollisionEvent::Started(a, b, _) => (a, b, true),
+            CollisionEvent::Stopped(a, b, _) => (a, b, false),
+        };
+        if let (Ok(mut trigger), Ok(_)) = (triggers.get_mut(*collider_a), player.get(*collider_b)) {
+            trigger.player_inside = inside;
+        }
+        if let (Ok(mut trigger), Ok(_)) = (triggers.get_mut(*collider_b), player.get(*collider_a)) {
+            trigger.player_inside = inside;
+        }
+    }
+}
+
+pub fn fire_triggers(
+    input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut triggers: Query<(&mut Trigger, &mut Sprite)>,
+    mut signals: EventWriter<Signal>,
+) {
+    for (mut trigger, mut sprite) in &mut triggers {
+        let active = match trigger.kind {
+            TriggerKind::Lever
+                if trigger.player_inside && input.just_pressed(settings.key_bindings.interact) =>
+            {
+                !trigger.active
+            }
+            TriggerKind::Lever => trigger.active,
+            TriggerKind::PressurePlate | TriggerKind::Region => trigger.player_inside,
+        };
+        if active == trigger.active {
+            continue;
+        }
+        trigger.active = active;
+        sprite.color = if active { Color::WHITE } else { Color::GRAY };
+
+        if trigger.once && trigger.fired {
+            continue;
+        }
+        // Releasing a lever or plate undoes what pressing it did,
+        // regions and toggles only ever react on the way in
+        let kind = match (active, trigger.mode) {
+            (true, mode) => mode,
+            (false, _) if trigger.kind == TriggerKind::Region => continue,
+            (false, SignalKind::Toggle) => continue,
+            (false, SignalKind::On) => SignalKind::Off,
+            (false, SignalKind::Off) => SignalKind::On,
+        };
+        trigger.fired = true;
+        for target in &trigger.targets {
+            signals.send(Signal {
+                target: target.clone(),
+                kind,
+            });
+        }
+    }
+}
+
+pub fn receive_signals(
+    mut signals: EventReader<Signal>,
+    mut receivers: Query<(&EntityInstance, &mut SignalReceiver)>,
+) {
+    for signal in signals.read() {
+        // Targets in levels that aren't loaded simply miss the signal
+        for (entity_instance, mut receiver) in &mut receivers {
+            if entity_instance.iid == signal.target {
+                receiver.powered = match signal.kind {
+                    SignalKind::On => true,
+                    SignalKind::Off => false,
+                    SignalKind::Toggle => !receiver.powered,
+                };
+            }
+        }
+    }
+}
+
+pub fn update_doors(
+    mut cmd: Commands,
+    player: Query<&Items, With<Player>>,
+    mut doors: Query<(Entity, &mut Door, &SignalReceiver, &mut Sprite)>,
+) {
+    let Ok(items) = player.get_single() else {
+        return;
+    };
+    for (entity, mut door, receiver, mut sprite) in &mut doors {
+        let has_key = door.key.as_ref().is_some_and(|key| items.0.contains(key));
+        let open = has_key || receiver.powered;
+        if open == door.open {
+            continue;
+        }
+        door.open = open;
+        if open {
+            cmd.entity(entity).insert(ColliderDisabled);
+            sprite.color.set_a(0.25);
+        } else {
+            cmd.entity(entity).remove::<ColliderDisabled>();
+            sprite.color.set_a(1.0);
+        }
+    }
+}
+
+pub fn move_platforms(
+    time: Res<Time>,
+    mut platforms: Query<(&Platform, &SignalReceiver, &mut Transform, &mut Velocity)>,
+) {
+    for (platform, receiver, mut transform, mut velocity) in &mut platforms {
+        let target = if receiver.powered {
+            platform.end
+        } else {
+            platform.start
+        };
+        let offset = target - transform.translation.truncate();
+        // Snap to the target once the next step would overshoot it
+        if offset.length() <= platform.speed * time.delta_seconds() {
+            transform.translation = target.extend(transform.translation.z);
+            velocity.linvel = Vec2::ZERO;
+        } else {
+            velocity.linvel = offset.normalize() * platform.speed;
+        }
+    }
+}
+
+pub fn record_world_state(
+    mut world_state: ResMut<WorldState>,
+    triggers: Query<(&Trigger, &EntityInstance), Changed<Trigger>>,
+    receivers: Query<
+        (&SignalReceiver, &EntityInstance),
+        (Changed<SignalReceiver>, Without<Spawner>),
+    >,
+    // Recorded every frame, the count of alive mobs changes without the spawner changing
+    spawners: Query<(Entity, &Spawner, &SignalReceiver, &EntityInstance)>,
+    mobs: Query<&SpawnedBy>,
+) {
+    for (trigger, entity_instance) in &triggers {
+        world_state.record(
+            Some(entity_instance),
+            EntityState::Triggered {
+                active: trigger.active,
+                fired: trigger.fired,
+            },
+        );
+    }
+    for (receiver, entity_instance) in &receivers {
+        world_state.record(
+            Some(entity_instance),
+            EntityState::Powered(receiver.powered),
+        );
+    }
+    for (entity, spawner, receiver, entity_instance) in &spawners {
+        let state = if spawner.cleared {
+            EntityState::Cleared
+        } else {
+            let alive = mobs
+                .iter()
+                .filter(|spawned_by| spawned_by.0 == entity)
+                .count();
+            EntityState::Spawning {
+                spawned: spawner.spawned.saturating_sub(alive as u32),
+                powered: receiver.powered,
+            }
+        };
+        world_state.record(Some(entity_instance), state);
+    }
+}
@@ -4,126 +4,105 @@
 #![allow(clippy::cast_precision_loss)]
 #![allow(clippy::cast_possible_truncation)]
 #![allow(clippy::too_many_lines)]
+#![allow(clippy::too_many_arguments)]
 
 use bevy::{asset::AssetMetaCheck, prelude::*, window::WindowResolution};
 use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+mod animation;
+mod audio;
+mod camera;
+mod combat;
 mod components;
 mod constants;
 mod debug;
+mod enemy;
+mod ghost;
+mod hud;
 mod ldtk_spawning;
-mod systems;
+mod level;
+mod map;
+mod minimap;
+mod pause;
+mod player;
+mod replay;
+mod ron_loader;
+mod save;
+mod settings;
+mod speedrun;
+mod stats;
+mod storage;
 
 fn main() {
+    let settings = settings::load();
+
     App::new()
         // # Resources
-        // - Touch-control
-        .insert_resource(components::GameTouches::default())
+        .insert_resource(components::GameRng::default())
+        // - Player preferences, stored on their own
+        .insert_resource(settings.clone())
         // - Bevy Engine settings
         .insert_resource(AssetMetaCheck::Never)
         .insert_resource(Msaa::Off)
-        // - Physics engine settings
+        // - Physics engine settings, stepped along with gameplay in `FixedUpdate`
+        .insert_resource(Time::<Fixed>::from_hz(constants::FIXED_TIMESTEP_HZ))
         .insert_resource(RapierConfiguration {
             gravity: Vec2::new(0.0, constants::GRAVITY),
-            ..Default::default()
-        })
-        // - LDTK settings
-        .insert_resource(LevelSelection::index(0))
-        .insert_resource(LdtkSettings {
-            level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
-                load_level_neighbors: true,
+            timestep_mode: TimestepMode::Fixed {
+                dt: (1.0 / constants::FIXED_TIMESTEP_HZ) as f32,
+                substeps: 1,
             },
-            set_clear_color: SetClearColor::FromLevelBackground,
             ..Default::default()
         })
-        // # LDTK settings
-        // - Register entities
-        .register_ldtk_entity::<components::PlayerBundle>("Player")
-        .register_ldtk_entity::<components::MobBundle>("Snake")
-        // - Register "collide" int grids
-        .register_ldtk_int_cell_for_layer::<components::WallBundle>(
-            "Collide",
-            constants::CollideEnums::RedBrick as i32,
-        )
-        .register_ldtk_int_cell_for_layer::<components::WallBundle>(
-            "Collide",
-            constants::CollideEnums::BlueBrick as i32,
-        )
-        .register_ldtk_int_cell_for_layer::<components::LadderBundle>(
-            "Collide",
-            constants::CollideEnums::Ladder as i32,
-        )
-        .register_ldtk_int_cell_for_layer::<components::WaterBundle>(
-            "Collide",
-            constants::CollideEnums::Water as i32,
-        )
         // # Systems
-        // - Startup systems
-        .add_systems(
-            Startup,
-            (setup_game_assets, systems::setup, systems::setup_camera).chain(),
+        .add_systems(Startup, setup_game_assets)
+        // - System sets, in the order they run each fixed step and each frame
+        .configure_sets(
+            FixedUpdate,
+            // Sensors pick up the last step's collisions before anything acts on them
+            (GameSet::Sensors, GameSet::Movement, GameSet::Combat)
+                .chain()
+                .before(PhysicsSet::SyncBackend),
         )
-        // - Delayed startup systems (Due to the way LDTK loads stuff in)
-        .add_systems(
+        .configure_sets(
             Update,
             (
-                ldtk_spawning::setup_player_components,
-                ldtk_spawning::fix_enemy_hitbox,
-                ldtk_spawning::fix_sprite_translation,
-                ldtk_spawning::add_patrol_animation_enemy,
-            ),
-        )
-        // - Update systems
-        .add_systems(
-            Update,
-            (
-                systems::spawn_ground_sensor,
-                systems::touch_input,
-                systems::spawn_wall_collision,
-                systems::player_movement,
-                systems::update_on_ground,
-                systems::update_ground_sensor_intersections,
-                systems::update_climb_intersection_detection,
-                systems::ignore_gravity_if_climbing,
-                systems::update_player_animations,
-                systems::update_level_selection,
-                systems::camera_fit_inside_current_level,
-                systems::clamp_velocity,
-                systems::apply_fake_friction_while_climbing,
-                systems::apply_fake_friction_on_ground,
-                systems::snap_player_to_climbable,
-                systems::update_climbing_status,
-                systems::check_touched_water,
-                systems::on_dead,
-                systems::patrol,
-                systems::advance_patrol_animation,
-            ),
-        )
-        // - More Update systems
-        .add_systems(
-            Update,
-            (
-                systems::check_touched_enemy,
-                systems::spawn_slash_sensor,
-                systems::update_slash_sensor_direction,
-                systems::update_slash_intersection,
-                systems::slash_kill,
-                systems::activate_slash,
-            ),
+                GameSet::Input,
+                GameSet::Sensors,
+                GameSet::Movement,
+                GameSet::Combat,
+                GameSet::Camera,
+                GameSet::Animation,
+            )
+                .chain(),
         )
         // # Plugins
+        // - Gameplay, ahead of the replay plugin which may start in another level
+        .add_plugins((
+            player::PlayerPlugin,
+            combat::CombatPlugin,
+            enemy::EnemyPlugin,
+            camera::CameraPlugin,
+            level::LevelPlugin,
+            animation::AnimationPlugin,
+            stats::StatsPlugin,
+            pause::PausePlugin,
+        ))
         .add_plugins((
             // - Default bevy plugin
             DefaultPlugins
                 .set(WindowPlugin {
                     primary_window: Some(Window {
-                        present_mode: bevy::window::PresentMode::AutoNoVsync,
+                        present_mode: settings::present_mode(&settings),
+                        mode: settings::window_mode(&settings),
                         resolution: WindowResolution::new(
-                            constants::BASE_RES.x,
-                            constants::BASE_RES.y,
+                            settings.resolution.x,
+                            settings.resolution.y,
                         ),
                         canvas: Some("#game".to_string()),
+                        // Follow the canvas size set by the page, so the UI can scale with it
+                        fit_canvas_to_parent: true,
                         ..default()
                     }),
                     ..default()
@@ -132,19 +111,57 @@ fn main() {
             // - LDTK
             LdtkPlugin,
             // - Physics engine
-            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(constants::PIXELS_PER_METER),
-            debug::add_plugin,
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(constants::PIXELS_PER_METER)
+                .in_fixed_schedule(),
+            debug::DebugPlugin,
+            audio::AudioPlugin,
+            hud::HudPlugin,
+            save::SavePlugin,
+            settings::SettingsPlugin,
+            map::MapPlugin,
+            minimap::MinimapPlugin,
+            speedrun::SpeedrunPlugin,
+            replay::ReplayPlugin,
+            ghost::GhostPlugin,
         ))
         .run();
 }
 
+/// Gameplay stages, ordered before the physics in `FixedUpdate` and again in `Update`
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameSet {
+    /// Reading the keyboard and touches
+    Input,
+    /// Turning collisions into sensor state
+    Sensors,
+    Movement,
+    Combat,
+    Camera,
+    Animation,
+}
+
 #[derive(Resource)]
 struct GameAssets {
     slash: Handle<Image>,
+    characters: Handle<Image>,
+    characters_layout: Handle<TextureAtlasLayout>,
 }
 
-fn setup_game_assets(mut cmd: Commands, asset_server: Res<AssetServer>) {
+fn setup_game_assets(
+    mut cmd: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
     cmd.insert_resource(GameAssets {
         slash: asset_server.load("swoosh.png"),
+        // Same sheet and grid as the "Characters" tileset in LDTK
+        characters: asset_server.load("characters.png"),
+        characters_layout: texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
+            Vec2 { x: 32.0, y: 32.0 },
+            23,
+            4,
+            None,
+            None,
+        )),
     });
 }
@@ -0,0 +1,418 @@
+use crate::components::Settings;
+use crate::constants;
+use crate::ghost::Ghosts;
+use crate::level::{RespawnPoint, VisitedLevels};
+use crate::pause::{PauseReason, PauseReasons};
+use crate::player::Player;
+use crate::settings::SettingsMenu;
+use crate::speedrun::format_time;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::time::Duration;
+
+/// The world map screen, with warping between visited levels
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapScreen>()
+            .add_systems(Startup, setup_map)
+            .add_systems(
+                PreUpdate,
+                map_input
+                    .after(InputSystem)
+                    .after(crate::replay::ReplayInput)
+                    .before(crate::settings::settings_menu_input),
+            )
+            .add_systems(
+                Update,
+                (
+                    track_visited_levels,
+                    build_map_levels,
+                    update_map,
+                    update_map_help,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The world map, opened with M
+#[derive(Resource, Default)]
+pub struct MapScreen {
+    pub open: bool,
+    /// Index of the level picked for warping
+    selected: usize,
+}
+
+/// Marker for the map's full screen backdrop
+#[derive(Component)]
+struct MapRoot;
+
+/// Holds the level rectangles, laid out in the world's bounds
+#[derive(Component, Default)]
+struct MapArea {
+    /// World space bounds of all levels, known once the LDTK project has loaded
+    bounds: Option<Rect>,
+}
+
+#[derive(Component)]
+struct MapLevel {
+    iid: String,
+    index: usize,
+    /// World space bounds of the level
+    bounds: Rect,
+}
+
+#[derive(Component)]
+struct MapPlayerDot;
+
+/// Shows whether the selected level has a ghost to race
+#[derive(Component)]
+struct MapGhostText;
+
+/// Lists the map's keys, in the chosen language
+#[derive(Component)]
+struct MapHelpText;
+
+/// Iid of the level the `LevelSelection` points at
+pub fn selected_level_iid(
+    level_selection: &LevelSelection,
+    ldtk_project: &LdtkProject,
+) -> Option<String> {
+    ldtk_project
+        .iter_raw_levels()
+        .enumerate()
+        .find(|(index, level)| level_selection.is_match(&LevelIndices::in_root(*index), level))
+        .map(|(_, level)| level.iid.clone())
+}
+
+/// World space bounds of a level, LDTK's y axis points down while Bevy's points up
+pub fn level_bounds(level: &ldtk::Level) -> Rect {
+    let min = Vec2::new(level.world_x as f32, -(level.world_y + level.px_hei) as f32);
+    Rect::from_corners(
+        min,
+        min + Vec2::new(level.px_wid as f32, level.px_hei as f32),
+    )
+}
+
+/// World space bounds of every level in the project together
+pub fn world_bounds(ldtk_project: &LdtkProject) -> Option<Rect> {
+    ldtk_project
+        .iter_raw_levels()
+        .map(level_bounds)
+        .reduce(|a, b| a.union(b))
+}
+
+/// Levels can be warped to once visited, or always in debug builds
+fn can_warp(visited_levels: &VisitedLevels, level_iid: &str) -> bool {
+    cfg!(debug_assertions) || visited_levels.0.contains(level_iid)
+}
+
+fn setup_map(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("Orbitron-Bold.ttf"),
+        font_size: 16.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            MapRoot,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.8)),
+                // above the HUD, below the settings menu
+                z_index: ZIndex::Global(i32::MAX - 2),
+                visibility: Visibility::Hidden,
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                MapArea::default(),
+                NodeBundle {
+                    style: Style {
+                        height: Val::Percent(80.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ));
+            parent.spawn((MapGhostText, TextBundle::from_section("", style.clone())));
+            parent.spawn((MapHelpText, TextBundle::from_section("", style)));
+        });
+}
+
+pub fn map_input(
+    mut input: ResMut<ButtonInput<KeyCode>>,
+    settings_menu: Res<SettingsMenu>,
+    mut map: ResMut<MapScreen>,
+    mut pause_reasons: ResMut<PauseReasons>,
+    levels: Query<&MapLevel>,
+    visited_levels: Res<VisitedLevels>,
+    respawn_point: Res<RespawnPoint>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut player: Query<(&mut Transform, &mut Velocity), With<Player>>,
+    mut ghosts: ResMut<Ghosts>,
+) {
+    if settings_menu.open {
+        return;
+    }
+    if input.just_pressed(KeyCode::KeyM) || (map.open && input.just_pressed(KeyCode::Escape)) {
+        map.open = !map.open;
+        if map.open {
+            pause_reasons.insert(PauseReason::Map);
+        } else {
+            pause_reasons.remove(PauseReason::Map);
+        }
+    }
+    if !map.open {
+        return;
+    }
+
+    let count = levels.iter().count().max(1);
+    if input.just_pressed(KeyCode::ArrowRight) || input.just_pressed(KeyCode::ArrowDown) {
+        map.selected = (map.selected + 1) % count;
+    }
+    if input.just_pressed(KeyCode::ArrowLeft) || input.just_pressed(KeyCode::ArrowUp) {
+        map.selected = (map.selected + count - 1) % count;
+    }
+
+    let selected = levels.iter().find(|level| level.index == map.selected);
+    if let (true, Some(level)) = (input.just_pressed(KeyCode::KeyG), selected) {
+        if !ghosts.enabled.remove(&level.iid) && ghosts.best(&level.iid).is_some() {
+            ghosts.enabled.insert(level.iid.clone());
+        }
+    }
+    if let (true, Some(level), Ok((mut transform, mut velocity))) = (
+        input.just_pressed(KeyCode::Enter),
+        selected,
+        player.get_single_mut(),
+    ) {
+        if can_warp(&visited_levels, &level.iid) {
+            // Land on the checkpoint if there is one, the middle of the level otherwise
+            transform.translation =
+                if respawn_point.level_iid.as_deref() == Some(level.iid.as_str()) {
+                    respawn_point.position
+                } else {
+                    level.bounds.center().extend(constants::DEFAULT_SPAWN.z)
+                };
+            velocity.linvel = Vec2::ZERO;
+            *level_selection = LevelSelection::iid(level.iid.clone());
+            map.open = false;
+            pause_reasons.remove(PauseReason::Map);
+        }
+    }
+
+    // Keep key presses meant for the map away from the game and the settings menu
+    input.reset_all();
+}
+
+fn track_visited_levels(
+    level_selection: Res<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut visited_levels: ResMut<VisitedLevels>,
+) {
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    if let Some(level_iid) = selected_level_iid(&level_selection, ldtk_project) {
+        if !visited_levels.0.contains(&level_iid) {
+            visited_levels.0.insert(level_iid);
+        }
+    }
+}
+
+/// Lays out a rectangle per level once the LDTK project has loaded
+fn build_map_levels(
+    mut commands: Commands,
+    mut areas: Query<(Entity, &mut MapArea, &mut Style)>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    for (entity, mut area, mut style) in &mut areas {
+        if area.bounds.is_some() {
+            continue;
+        }
+
+        let Some(bounds) = world_bounds(ldtk_project) else {
+            continue;
+        };
+        let levels = ldtk_project
+            .iter_raw_levels()
+            .map(|level| (level, level_bounds(level)))
+            .collect::<Vec<_>>();
+        area.bounds = Some(bounds);
+        style.aspect_ratio = Some(bounds.width() / bounds.height());
+
+        let percent = |value: f32, total: f32| Val::Percent(value / total * 100.);
+        commands.entity(entity).with_children(|parent| {
+            for (index, (level, level_bounds)) in levels.iter().enumerate() {
+                parent
+                    .spawn((
+                        MapLevel {
+                            iid: level.iid.clone(),
+                            index,
+                            bounds: *level_bounds,
+                        },
+                        NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                left: percent(level_bounds.min.x - bounds.min.x, bounds.width()),
+                                top: percent(bounds.max.y - level_bounds.max.y, bounds.height()),
+                                width: percent(level_bounds.width(), bounds.width()),
+                                height: percent(level_bounds.height(), bounds.height()),
+                                border: UiRect::all(Val::Px(2.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            level.identifier.replace('_', " ").to_uppercase(),
+                            TextStyle {
+                                font_size: 12.0,
+                                color: Color::BLACK,
+                                ..Default::default()
+                            },
+                        ));
+                    });
+            }
+            parent.spawn((
+                MapPlayerDot,
+                NodeBundle {
+                    background_color: BackgroundColor(Color::RED),
+                    z_index: ZIndex::Local(1),
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(6.0),
+                        height: Val::Px(6.0),
+                        margin: UiRect::all(Val::Px(-3.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ));
+        });
+    }
+}
+
+fn update_map_help(settings: Res<Settings>, mut texts: Query<&mut Text, With<MapHelpText>>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.sections[0].value = settings
+            .language
+            .tr("ARROWS: SELECT   ENTER: WARP   G: GHOST   M: CLOSE")
+            .to_string();
+    }
+}
+
+fn update_map(
+    map: Res<MapScreen>,
+    visited_levels: Res<VisitedLevels>,
+    level_selection: Res<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut roots: Query<&mut Visibility, With<MapRoot>>,
+    areas: Query<&MapArea>,
+    mut levels: Query<(&MapLevel, &mut BackgroundColor, &mut BorderColor)>,
+    mut dots: Query<&mut Style, With<MapPlayerDot>>,
+    player: Query<&Transform, With<Player>>,
+    ghosts: Res<Ghosts>,
+    mut ghost_texts: Query<&mut Text, With<MapGhostText>>,
+    settings: Res<Settings>,
+) {
+    if map.is_changed() {
+        for mut visibility in &mut roots {
+            *visibility = if map.open {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+    if !map.open {
+        return;
+    }
+
+    let current = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+        .and_then(|ldtk_project| selected_level_iid(&level_selection, ldtk_project));
+    for (level, mut background, mut border) in &mut levels {
+        background.0 = if current.as_ref() == Some(&level.iid) {
+            Color::GOLD
+        } else if visited_levels.0.contains(&level.iid) {
+            Color::SILVER
+        } else {
+            Color::DARK_GRAY
+        };
+        border.0 = match (
+            level.index == map.selected,
+            can_warp(&visited_levels, &level.iid),
+        ) {
+            (true, true) => Color::WHITE,
+            (true, false) => Color::RED,
+            (false, _) => Color::NONE,
+        };
+
+        if level.index == map.selected {
+            let language = settings.language;
+            let enabled = ghosts.enabled.contains(&level.iid);
+            let ghost = match ghosts.best(&level.iid) {
+                Some(run) => format!(
+                    "{}  {}",
+                    language.tr(if enabled { "GHOST: ON" } else { "GHOST: OFF" }),
+                    format_time(Duration::from_secs_f32(run.duration()))
+                ),
+                None => language.tr("NO GHOST YET").to_string(),
+            };
+            for mut text in &mut ghost_texts {
+                if text.sections[0].value != ghost {
+                    text.sections[0].value.clone_from(&ghost);
+                }
+            }
+        }
+    }
+
+    let (Ok(player), Some(bounds)) = (
+        player.get_single(),
+        areas.iter().find_map(|area| area.bounds),
+    ) else {
+        return;
+    };
+    let position = player.translation.truncate();
+    for mut style in &mut dots {
+        style.left = Val::Percent((position.x - bounds.min.x) / bounds.width() * 100.);
+        style.top = Val::Percent((bounds.max.y - position.y) / bounds.height() * 100.);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::constants::CollideEnums;
+use crate::enemy::Enemy;
+use crate::map::{level_bounds, world_bounds};
+use crate::player::Player;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_ecs_ldtk::prelude::*;
+
+/// Tile size of the `Collide` layer
+const TILE_SIZE: f32 = 16.0;
+/// How many tiles around the player get revealed
+const REVEAL_RADIUS: i32 = 7;
+/// Screen pixels per tile
+const MINIMAP_SCALE: f32 = 3.0;
+const MINIMAP_SIZE: Vec2 = Vec2::new(192.0, 120.0);
+/// Enemies shown at once, a room rarely has more
+const MAX_ENEMY_DOTS: usize = 8;
+
+/// Corner minimap of the current level, revealed as the player explores
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Minimap>()
+            .add_systems(Startup, setup_minimap)
+            .add_systems(
+                Update,
+                (
+                    create_minimap_image,
+                    rasterize_collide_cells,
+                    reveal_minimap,
+                    update_minimap_view,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// A texture of the whole world with a pixel per `Collide` tile,
+/// each tile stays transparent until the player has been near it
+#[derive(Resource, Default)]
+struct Minimap {
+    image: Handle<Image>,
+    /// World space bounds the image covers, known once the LDTK project has loaded
+    bounds: Option<Rect>,
+    /// Size of the image in tiles
+    size: UVec2,
+    /// `Collide` int grid values, 0 where there is nothing
+    tiles: Vec<i32>,
+    revealed: Vec<bool>,
+    /// Tiles to redraw in the image
+    dirty: Vec<usize>,
+}
+
+impl Minimap {
+    /// Index of a tile in the image, if it's inside the map
+    fn index(&self, tile: IVec2) -> Option<usize> {
+        let (Ok(x), Ok(y)) = (u32::try_from(tile.x), u32::try_from(tile.y)) else {
+            return None;
+        };
+        // Image rows go top to bottom, while tiles count up from the bottom
+        (x < self.size.x && y < self.size.y)
+            .then(|| ((self.size.y - 1 - y) * self.size.x + x) as usize)
+    }
+
+    /// Tile under a world position
+    fn tile(&self, position: Vec2) -> IVec2 {
+        let bounds = self.bounds.unwrap_or_default();
+        ((position - bounds.min) / TILE_SIZE).floor().as_ivec2()
+    }
+}
+
+fn tile_color(value: i32, revealed: bool) -> [u8; 4] {
+    if !revealed {
+        return [0, 0, 0, 0];
+    }
+    match value {
+        v if v == CollideEnums::RedBrick as i32 => [196, 110, 110, 255],
+        v if v == CollideEnums::BlueBrick as i32 => [110, 140, 196, 255],
+        v if v == CollideEnums::Water as i32 => [40, 90, 230, 255],
+        v if v == CollideEnums::Ladder as i32 => [200, 160, 60, 255],
+        _ => [20, 20, 20, 200],
+    }
+}
+
+#[derive(Component)]
+struct MinimapImage;
+
+#[derive(Component)]
+struct MinimapEnemyDot;
+
+fn setup_minimap(mut commands: Commands) {
+    let dot = |color: Color| NodeBundle {
+        background_color: BackgroundColor(color),
+        z_index: ZIndex::Local(1),
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Px(4.0),
+            height: Val::Px(4.0),
+            margin: UiRect::all(Val::Px(-2.0)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // bottom-right corner, out of the way of the rest of the HUD
+    commands
+        .spawn(NodeBundle {
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            border_color: BorderColor(Color::WHITE.with_a(0.5)),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(1.),
+                bottom: Val::Percent(1.),
+                width: Val::Px(MINIMAP_SIZE.x),
+                height: Val::Px(MINIMAP_SIZE.y),
+                border: UiRect::all(Val::Px(1.0)),
+                overflow: Overflow::clip(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                MinimapImage,
+                ImageBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ));
+            // The map scrolls under the player, who stays in the middle
+            let mut player_dot = dot(Color::RED);
+            player_dot.style.left = Val::Px(MINIMAP_SIZE.x / 2.);
+            player_dot.style.top = Val::Px(MINIMAP_SIZE.y / 2.);
+            parent.spawn(player_dot);
+            for _ in 0..MAX_ENEMY_DOTS {
+                parent.spawn((MinimapEnemyDot, dot(Color::ORANGE)));
+            }
+        });
+}
+
+fn create_minimap_image(
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    mut minimap_images: Query<(&mut UiImage, &mut Style), With<MinimapImage>>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    if minimap.bounds.is_some() {
+        return;
+    }
+    let Some(bounds) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+        .and_then(world_bounds)
+    else {
+        return;
+    };
+
+    let size = (bounds.size() / TILE_SIZE).ceil().as_uvec2();
+    let tile_count = (size.x * size.y) as usize;
+    minimap.bounds = Some(bounds);
+    minimap.size = size;
+    minimap.tiles = vec![0; tile_count];
+    minimap.revealed = vec![false; tile_count];
+    minimap.image = images.add(Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &tile_color(0, false),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+
+    for (mut ui_image, mut style) in &mut minimap_images {
+        ui_image.texture = minimap.image.clone();
+        style.width = Val::Px(size.x as f32 * MINIMAP_SCALE);
+        style.height = Val::Px(size.y as f32 * MINIMAP_SCALE);
+    }
+}
+
+/// Copies `Collide` cells into the minimap as their levels spawn
+fn rasterize_collide_cells(
+    mut minimap: ResMut<Minimap>,
+    cells: Query<(&IntGridCell, &GridCoords, &Parent), Added<IntGridCell>>,
+    layers: Query<(&LayerMetadata, &Parent)>,
+    levels: Query<&LevelIid>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    for (cell, grid_coords, parent) in &cells {
+        // An intgrid tile's direct parent is its layer, and the layer's parent is the level
+        let Ok((layer, layer_parent)) = layers.get(parent.get()) else {
+            continue;
+        };
+        if layer.identifier != "Collide" {
+            continue;
+        }
+        let Some(level) = levels
+            .get(layer_parent.get())
+            .ok()
+            .and_then(|level_iid| ldtk_project.get_raw_level_by_iid(&level_iid.to_string()))
+        else {
+            continue;
+        };
+        let tile = minimap.tile(level_bounds(level).min) + IVec2::new(grid_coords.x, grid_coords.y);
+        if let Some(index) = minimap.index(tile) {
+            minimap.tiles[index] = cell.value;
+            if minimap.revealed[index] {
+                minimap.dirty.push(index);
+            }
+        }
+    }
+}
+
+fn reveal_minimap(
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    player: Query<&GlobalTransform, With<Player>>,
+) {
+    if minimap.bounds.is_none() {
+        return;
+    }
+    if let Ok(player) = player.get_single() {
+        let center = minimap.tile(player.translation().truncate());
+        for y in -REVEAL_RADIUS..=REVEAL_RADIUS {
+            for x in -REVEAL_RADIUS..=REVEAL_RADIUS {
+                if x * x + y * y > REVEAL_RADIUS * REVEAL_RADIUS {
+                    continue;
+                }
+                if let Some(index) = minimap.index(center + IVec2::new(x, y)) {
+                    if !minimap.revealed[index] {
+                        minimap.revealed[index] = true;
+                        minimap.dirty.push(index);
+                    }
+                }
+            }
+        }
+    }
+
+    // Only touch the image when something changed, every change re-uploads it
+    if minimap.dirty.is_empty() {
+        return;
+    }
+    let minimap = &mut *minimap;
+    let Some(image) = images.get_mut(&minimap.image) else {
+        return;
+    };
+    for index in minimap.dirty.drain(..) {
+        image.data[index * 4..index * 4 + 4]
+            .copy_from_slice(&tile_color(minimap.tiles[index], minimap.revealed[index]));
+    }
+}
+
+fn update_minimap_view(
+    minimap: Res<Minimap>,
+    player: Query<&GlobalTransform, With<Player>>,
+    enemies: Query<&GlobalTransform, With<Enemy>>,
+    mut minimap_images: Query<&mut Style, (With<MinimapImage>, Without<MinimapEnemyDot>)>,
+    mut enemy_dots: Query<(&mut Style, &mut Visibility), With<MinimapEnemyDot>>,
+) {
+    let (Some(bounds), Ok(player)) = (minimap.bounds, player.get_single()) else {
+        return;
+    };
+    // Screen offset of a world position, relative to the minimap's top-left corner
+    let player = player.translation().truncate();
+    let to_screen = |position: Vec2| {
+        let offset = (position - player) / TILE_SIZE * MINIMAP_SCALE;
+        MINIMAP_SIZE / 2. + Vec2::new(offset.x, -offset.y)
+    };
+
+    for mut style in &mut minimap_images {
+        let top_left = to_screen(Vec2::new(bounds.min.x, bounds.max.y));
+        style.left = Val::Px(top_left.x);
+        style.top = Val::Px(top_left.y);
+    }
+
+    // Enemies only show up on explored tiles near enough to fit on the minimap
+    let mut visible_enemies = enemies
+        .iter()
+        .map(|enemy| enemy.translation().truncate())
+        .filter(|position| {
+            minimap
+                .index(minimap.tile(*position))
+                .is_some_and(|index| minimap.revealed[index])
+        })
+        .map(to_screen)
+        .filter(|screen| screen.cmpge(Vec2::ZERO).all() && screen.cmple(MINIMAP_SIZE).all());
+    for (mut style, mut visibility) in &mut enemy_dots {
+        if let Some(screen) = visible_enemies.next() {
+            style.left = Val::Px(screen.x);
+            style.top = Val::Px(screen.y);
+            *visibility = Visibility::Inherited;
+        } else {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
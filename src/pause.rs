@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::collections::HashSet;
+
+/// Game time and physics stop while anything asks for a pause
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseReasons>()
+            .add_systems(PostUpdate, apply_pause_reasons);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PauseReason {
+    /// A `PanFreeze` camera transition between levels
+    CameraPan,
+    SettingsMenu,
+    Map,
+}
+
+/// Everything currently pausing the game, so one of them ending doesn't resume the game under another
+#[derive(Resource, Default, Debug)]
+pub struct PauseReasons(HashSet<PauseReason>);
+
+impl PauseReasons {
+    pub fn insert(&mut self, reason: PauseReason) {
+        self.0.insert(reason);
+    }
+
+    pub fn remove(&mut self, reason: PauseReason) {
+        self.0.remove(&reason);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+pub fn apply_pause_reasons(
+    reasons: Res<PauseReasons>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    if !reasons.is_changed() {
+        return;
+    }
+    let paused = reasons.is_paused();
+    if paused {
+        virtual_time.pause();
+    } else {
+        virtual_time.unpause();
+    }
+    rapier_config.physics_pipeline_active = !paused;
+}
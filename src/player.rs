@@ -0,0 +1,410 @@
+use crate::animation::AnimationParams;
+use crate::audio::Sfx;
+use crate::combat::{CanDie, Pickup};
+use crate::components::{ColliderBundle, IsLdtkEntity, Settings};
+use crate::level::Water;
+use crate::stats::{CharacterKind, StatOverrides};
+use crate::{ldtk_spawning, GameSet};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// Player movement, climbing and the sensors feeding them
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameTouches>()
+            .register_ldtk_entity::<PlayerBundle>("Player")
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        (update_ground_sensor_intersections, update_on_ground).chain(),
+                        update_climb_intersection_detection,
+                        check_touched_water,
+                    )
+                        .in_set(GameSet::Sensors),
+                    (
+                        update_climbing_status,
+                        ignore_gravity_if_climbing,
+                        player_movement,
+                        apply_fake_friction_while_climbing,
+                        apply_fake_friction_on_ground,
+                        snap_player_to_climbable,
+                        clamp_velocity,
+                    )
+                        .chain()
+                        .in_set(GameSet::Movement),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    ldtk_spawning::setup_player_components,
+                    touch_input.in_set(GameSet::Input),
+                    spawn_ground_sensor.in_set(GameSet::Sensors),
+                ),
+            );
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct PlayerBundle {
+    #[sprite_sheet_bundle]
+    sprite_sheet_bundle: SpriteSheetBundle,
+
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    pub player: Player,
+    #[worldly]
+    pub worldly: Worldly,
+    pub climber: Climber,
+    pub ground_detection: GroundDetection,
+
+    // Build Items Component manually by using `impl From<&EntityInstance>`
+    #[from_entity_instance]
+    items: Items,
+
+    // Movement stats and animations are looked up by the entity's LDTK identifier
+    #[from_entity_instance]
+    character_kind: CharacterKind,
+    #[from_entity_instance]
+    stat_overrides: StatOverrides,
+
+    // The whole EntityInstance can be stored directly as an EntityInstance component
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+    pub entity: IsLdtkEntity,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Climbable;
+
+#[derive(Component)]
+pub struct GroundSensor {
+    pub ground_detection_entity: Entity,
+    pub intersecting_ground_entities: HashSet<Entity>,
+}
+
+#[derive(Resource, Default)]
+pub struct GameTouches(pub Vec<Vec2>);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Player;
+
+#[derive(Clone, PartialEq, Debug, Default, Component)]
+pub struct Climber {
+    pub climbing: bool,
+    pub intersecting_climbables: HashMap<Entity, GlobalTransform>,
+}
+
+#[derive(Clone, Component, Debug, Eq, Default, PartialEq)]
+pub struct Items(pub Vec<String>);
+
+#[derive(Clone, Default, Component)]
+pub struct GroundDetection {
+    pub on_ground: bool,
+}
+
+/// Horizontal acceleration, in pixels per second squared
+#[derive(Clone, Default, Component)]
+pub struct AccelerationStat(pub f32);
+
+#[derive(Clone, Default, Component)]
+pub struct MaxSpeedStat(pub Vec2);
+
+#[derive(Clone, Default, Component)]
+pub struct JumpForceStat(pub f32);
+
+/// How fast horizontal speed decays on the ground, per second
+#[derive(Clone, Default, Component)]
+pub struct FakeGroundFrictionStat(pub f32);
+
+pub fn touch_input(
+    touches: Res<Touches>,
+    mut game_touches: ResMut<GameTouches>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    let (camera, camera_transform) = q_camera.single();
+    game_touches.0 = touches
+        .iter()
+        .filter_map(|t| camera.viewport_to_world_2d(camera_transform, t.position()))
+        .collect::<Vec<Vec2>>();
+}
+
+pub fn player_movement(
+    time: Res<Time>,
+    input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut query: Query<
+        (
+            &AccelerationStat,
+            &JumpForceStat,
+            &mut Velocity,
+            &Climber,
+            &GroundDetection,
+            Option<&mut AnimationParams>,
+        ),
+        With<Player>,
+    >,
+    mut sfx: EventWriter<Sfx>,
+) {
+    let keys = &settings.key_bindings;
+    for (acceleration_stat, jump_force_stat, mut velocity, climber, ground_detection, params) in
+        &mut query
+    {
+        let right = if input.pressed(keys.right) { 1. } else { 0. };
+        let left = if input.pressed(keys.left) { 1. } else { 0. };
+
+        velocity.linvel.x += (right - left) * acceleration_stat.0 * time.delta_seconds();
+
+        if climber.climbing {
+            let up = if input.pressed(keys.up) { 1. } else { 0. };
+            let down = if input.pressed(keys.down) { 1. } else { 0. };
+            velocity.linvel.y = (up - down) * 100.;
+        }
+
+        if input.pressed(keys.jump) && (ground_detection.on_ground) {
+            // The ground sensor can lag a frame behind take-off, so only the first push counts
+            if velocity.linvel.y < jump_force_stat.0 {
+                sfx.send(Sfx::Jump);
+                if let Some(mut params) = params {
+                    params.trigger("jump");
+                }
+            }
+            velocity.linvel.y = jump_force_stat.0;
+        }
+    }
+}
+
+pub fn update_climbing_status(
+    input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut query: Query<&mut Climber, With<Player>>,
+) {
+    let keys = &settings.key_bindings;
+    for mut climber in &mut query {
+        if climber.intersecting_climbables.is_empty() {
+            climber.climbing = false;
+        } else if input.pressed(keys.up) || input.pressed(keys.down) {
+            climber.climbing = true;
+        } else if input.pressed(keys.jump) {
+            climber.climbing = false;
+        }
+    }
+}
+
+pub fn snap_player_to_climbable(
+    mut query: Query<(&mut Transform, &mut Velocity, &Climber), With<Player>>,
+) {
+    for (mut transform, mut velocity, climber) in &mut query {
+        if climber.climbing && velocity.linvel.y != 0.0 {
+            velocity.linvel.x = 0.0;
+            // The nearest ladder, with ties broken by entity so replays pick the same one
+            let player_x = transform.translation.x;
+            let climb_x_location = climber
+                .intersecting_climbables
+                .iter()
+                .map(|(entity, climbable)| {
+                    let x = climbable.translation().x + 0.4 * climbable.compute_transform().scale.x;
+                    (entity, x)
+                })
+                .min_by(|(entity_a, x_a), (entity_b, x_b)| {
+                    (x_a - player_x)
+                        .abs()
+                        .total_cmp(&(x_b - player_x).abs())
+                        .then(entity_a.cmp(entity_b))
+                })
+                .map(|(_, x)| x);
+            if let Some(x) = climb_x_location {
+                transform.translation.x = x + 0.4 * transform.scale.x;
+            }
+        }
+    }
+}
+
+pub fn apply_fake_friction_while_climbing(
+    time: Res<Time>,
+    mut query: Query<(&FakeGroundFrictionStat, &mut Velocity, &Climber)>,
+) {
+    for (fake_friction, mut velocity, climber) in &mut query {
+        if climber.climbing {
+            velocity.linvel.x *= (-fake_friction.0 * time.delta_seconds()).exp();
+        }
+    }
+}
+
+pub fn apply_fake_friction_on_ground(
+    time: Res<Time>,
+    mut query: Query<(&FakeGroundFrictionStat, &mut Velocity, &GroundDetection)>,
+) {
+    for (fake_friction, mut velocity, ground_detection) in &mut query {
+        if ground_detection.on_ground {
+            velocity.linvel.x *= (-fake_friction.0 * time.delta_seconds()).exp();
+        }
+    }
+}
+
+pub fn clamp_velocity(mut query: Query<(&MaxSpeedStat, &mut Velocity)>) {
+    for (max_speed, mut velocity) in &mut query {
+        velocity.linvel.x = velocity.linvel.x.clamp(-max_speed.0.x, max_speed.0.x);
+        velocity.linvel.y = velocity.linvel.y.clamp(-max_speed.0.y, max_speed.0.y);
+    }
+}
+
+pub fn spawn_ground_sensor(
+    mut commands: Commands,
+    detect_ground_for: Query<(Entity, &Collider), Added<GroundDetection>>,
+) {
+    for (entity, shape) in &detect_ground_for {
+        if let Some(round) = shape.as_capsule() {
+            let half_extents_x = round.radius();
+            let half_extents_y = round.half_height() + half_extents_x;
+
+            let detector_shape = Collider::cuboid(half_extents_x / 2.0, 2.);
+
+            let sensor_translation = Vec3::new(0., -half_extents_y, 0.);
+            commands.entity(entity).with_children(|builder| {
+                builder
+                    .spawn_empty()
+                    .insert(ActiveEvents::COLLISION_EVENTS)
+                    .insert(ActiveCollisionTypes::all())
+                    .insert(detector_shape)
+                    .insert(Sensor)
+                    .insert(Transform::from_translation(sensor_translation))
+                    .insert(GlobalTransform::default())
+                    .insert(GroundSensor {
+                        ground_detection_entity: entity,
+                        intersecting_ground_entities: HashSet::new(),
+                    });
+            });
+        }
+    }
+}
+
+pub fn update_on_ground(
+    mut ground_detectors: Query<(&mut GroundDetection, Has<Player>)>,
+    ground_sensors: Query<&GroundSensor, Changed<GroundSensor>>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    for sensor in &ground_sensors {
+        if let Ok((mut ground_detection, is_player)) =
+            ground_detectors.get_mut(sensor.ground_detection_entity)
+        {
+            let on_ground = !sensor.intersecting_ground_entities.is_empty();
+            if is_player && on_ground && !ground_detection.on_ground {
+                sfx.send(Sfx::Land);
+            }
+            ground_detection.on_ground = on_ground;
+        }
+    }
+}
+
+pub fn update_ground_sensor_intersections(
+    mut ground_sensors: Query<&mut GroundSensor>,
+    mut collisions: EventReader<CollisionEvent>,
+    collidables: Query<Entity, (With<Collider>, Without<Sensor>, Without<Pickup>)>,
+) {
+    for collision_event in collisions.read() {
+        match collision_event {
+            CollisionEvent::Started(e1, e2, _) => {
+                if collidables.contains(*e1) {
+                    if let Ok(mut sensor) = ground_sensors.get_mut(*e2) {
+                        sensor.intersecting_ground_entities.insert(*e1);
+                    }
+                } else if collidables.contains(*e2) {
+                    if let Ok(mut sensor) = ground_sensors.get_mut(*e1) {
+                        sensor.intersecting_ground_entities.insert(*e2);
+                    }
+                }
+            }
+            CollisionEvent::Stopped(e1, e2, _) => {
+                if collidables.contains(*e1) {
+                    if let Ok(mut sensor) = ground_sensors.get_mut(*e2) {
+                        sensor.intersecting_ground_entities.remove(e1);
+                    }
+                } else if collidables.contains(*e2) {
+                    if let Ok(mut sensor) = ground_sensors.get_mut(*e1) {
+                        sensor.intersecting_ground_entities.remove(e2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn update_climb_intersection_detection(
+    mut climbers: Query<&mut Climber>,
+    climbables: Query<(Entity, &GlobalTransform), With<Climbable>>,
+    mut collisions: EventReader<CollisionEvent>,
+) {
+    for collision in collisions.read() {
+        match collision {
+            CollisionEvent::Started(collider_a, collider_b, _) => {
+                if let (Ok(mut climber), Ok(climbable)) =
+                    (climbers.get_mut(*collider_a), climbables.get(*collider_b))
+                {
+                    climber
+                        .intersecting_climbables
+                        .insert(climbable.0, *climbable.1);
+                }
+                if let (Ok(mut climber), Ok(climbable)) =
+                    (climbers.get_mut(*collider_b), climbables.get(*collider_a))
+                {
+                    climber
+                        .intersecting_climbables
+                        .insert(climbable.0, *climbable.1);
+                };
+            }
+            CollisionEvent::Stopped(collider_a, collider_b, _) => {
+                if let (Ok(mut climber), Ok(climbable)) =
+                    (climbers.get_mut(*collider_a), climbables.get(*collider_b))
+                {
+                    climber.intersecting_climbables.remove(&climbable.0);
+                }
+
+                if let (Ok(mut climber), Ok(climbable)) =
+                    (climbers.get_mut(*collider_b), climbables.get(*collider_a))
+                {
+                    climber.intersecting_climbables.remove(&climbable.0);
+                }
+            }
+        }
+    }
+}
+
+pub fn ignore_gravity_if_climbing(
+    mut query: Query<(&Climber, &mut GravityScale), Changed<Climber>>,
+) {
+    for (climber, mut gravity_scale) in &mut query {
+        if climber.climbing {
+            gravity_scale.0 = 0.0;
+        } else {
+            gravity_scale.0 = 1.0;
+        }
+    }
+}
+
+pub fn check_touched_water(
+    mut player: Query<&mut CanDie, With<Player>>,
+    waters: Query<(Entity, &GlobalTransform), With<Water>>,
+    mut collisions: EventReader<CollisionEvent>,
+    mut sfx: EventWriter<Sfx>,
+) {
+    for collision in collisions.read() {
+        if let CollisionEvent::Started(collider_a, collider_b, _) = collision {
+            if let (Ok(mut player), Ok(_)) = (player.get_mut(*collider_a), waters.get(*collider_b))
+            {
+                player.is_dead = true;
+                sfx.send(Sfx::Splash);
+            }
+            if let (Ok(mut player), Ok(_)) = (player.get_mut(*collider_b), waters.get(*collider_a))
+            {
+                player.is_dead = true;
+                sfx.send(Sfx::Splash);
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// Deterministic input recording, to reproduce bug reports:
+// `--record <file>` records a run from launch, F6 writes it out (it's also written on exit),
+// `--replay <file>` plays it back frame by frame.
+
+use crate::components::{Action, GameRng, Settings};
+use crate::constants::RNG_SEED;
+use bevy::app::AppExit;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::{PresentMode, PrimaryWindow};
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Bump when the layout of `Recording` changes
+pub const RECORDING_VERSION: u32 = 1;
+/// Every frame advances the game clock by exactly this much while recording or replaying
+const REPLAY_TIMESTEP: f64 = 1. / 60.;
+
+/// Recording and replaying input, picked from the command line
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let replay = InputReplay::from_args(std::env::args());
+        if let InputReplay::Replaying { recording, .. } = &replay {
+            app.insert_resource(GameRng::from_seed(recording.seed))
+                .insert_resource(LevelSelection::index(recording.start_level));
+        }
+        // Rapier's timestep reads the same clock, so the simulation steps identically too
+        if replay.is_active() {
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                REPLAY_TIMESTEP,
+            )))
+            .add_systems(Startup, lock_frame_rate);
+        }
+        app.insert_resource(replay)
+            .configure_sets(PreUpdate, ReplayInput.after(InputSystem))
+            .add_systems(PreUpdate, apply_input_replay.in_set(ReplayInput))
+            .add_systems(Last, write_recording_on_exit);
+    }
+}
+
+/// Replayed input is in place by the end of this set, so anything reading the keyboard
+/// in `PreUpdate` goes after it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplayInput;
+
+/// Everything needed to play a run back: it always starts from a new game,
+/// and loading a save is refused while recording, so nothing else needs storing
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recording {
+    pub version: u32,
+    pub seed: u64,
+    /// Index of the level the run starts in
+    pub start_level: usize,
+    /// Actions held on each frame, as bit masks in `Action::ALL` order
+    pub frames: Vec<u8>,
+}
+
+impl Recording {
+    fn read(path: &Path) -> Option<Recording> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| error!("Could not read recording {}: {err}", path.display()))
+            .ok()?;
+        match ron::from_str::<Recording>(&text) {
+            Ok(recording) if recording.version > RECORDING_VERSION => {
+                error!("Recording {} is from a newer version", path.display());
+                None
+            }
+            Ok(recording) => Some(recording),
+            Err(err) => {
+                error!("Recording {} is corrupted: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    fn write(&self, path: &Path) {
+        // Not pretty printed, a frame per line would be huge
+        let text = ron::to_string(self).expect("recording should always serialize");
+        match std::fs::write(path, text) {
+            Ok(()) => info!(
+                "Wrote {} frames of input to {}",
+                self.frames.len(),
+                path.display()
+            ),
+            Err(err) => warn!("Could not write recording {}: {err}", path.display()),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub enum InputReplay {
+    #[default]
+    Off,
+    Recording {
+        path: PathBuf,
+        recording: Recording,
+        held: u8,
+    },
+    Replaying {
+        recording: Recording,
+        frame: usize,
+        held: u8,
+    },
+}
+
+impl InputReplay {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--record", Some(path)) => {
+                    info!("Recording input to {path}, press F6 to write it");
+                    return InputReplay::Recording {
+                        path: path.into(),
+                        recording: Recording {
+                            version: RECORDING_VERSION,
+                            seed: RNG_SEED,
+                            start_level: 0,
+                            frames: Vec::new(),
+                        },
+                        held: 0,
+                    };
+                }
+                ("--replay", Some(path)) => {
+                    if let Some(recording) = Recording::read(Path::new(&path)) {
+                        info!("Replaying {} frames of input", recording.frames.len());
+                        return InputReplay::Replaying {
+                            recording,
+                            frame: 0,
+                            held: 0,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+        InputReplay::Off
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self, InputReplay::Off)
+    }
+}
+
+fn action_bit(index: usize) -> u8 {
+    1 << index
+}
+
+/// A fixed timestep only keeps real speed when frames come at a steady rate
+fn lock_frame_rate(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    for mut window in &mut windows {
+        window.present_mode = PresentMode::AutoVsync;
+    }
+}
+
+/// Records or plays back the held actions, then leaves only those keys pressed.
+/// Both sides see exactly the same input, so menus, the map and saving are off while active.
+fn apply_input_replay(
+    mut input: ResMut<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut replay: ResMut<InputReplay>,
+    mut time_update_strategy: ResMut<TimeUpdateStrategy>,
+) {
+    let (held, previous) = match &mut *replay {
+        InputReplay::Off => return,
+        InputReplay::Recording {
+            path,
+            recording,
+            held,
+        } => {
+            if input.just_pressed(KeyCode::F6) {
+                recording.write(path);
+            }
+            let previous = *held;
+            *held = Action::ALL
+                .into_iter()
+                .enumerate()
+                .filter(|(_, action)| input.pressed(settings.key_bindings.key(*action)))
+                .fold(0, |mask, (index, _)| mask | action_bit(index));
+            recording.frames.push(*held);
+            (*held, previous)
+        }
+        InputReplay::Replaying {
+            recording,
+            frame,
+            held,
+        } => {
+            let Some(next) = recording.frames.get(*frame).copied() else {
+                info!("Replay finished, handing control back");
+                *replay = InputReplay::Off;
+                *time_update_strategy = TimeUpdateStrategy::Automatic;
+                input.reset_all();
+                return;
+            };
+            *frame += 1;
+            let previous = *held;
+            *held = next;
+            (next, previous)
+        }
+    };
+
+    // Rebuild last frame's state first, so just pressed and just released come out right
+    input.reset_all();
+    for (index, action) in Action::ALL.into_iter().enumerate() {
+        if previous & action_bit(index) != 0 {
+            input.press(settings.key_bindings.key(action));
+        }
+    }
+    input.clear();
+    // Releases go first, in case two actions share a key
+    for (index, action) in Action::ALL.into_iter().enumerate() {
+        if held & action_bit(index) == 0 {
+            input.release(settings.key_bindings.key(action));
+        }
+    }
+    for (index, action) in Action::ALL.into_iter().enumerate() {
+        if held & action_bit(index) != 0 {
+            input.press(settings.key_bindings.key(action));
+        }
+    }
+}
+
+fn write_recording_on_exit(mut exits: EventReader<AppExit>, replay: Res<InputReplay>) {
+    if exits.read().count() == 0 {
+        return;
+    }
+    if let InputReplay::Recording {
+        path, recording, ..
+    } = replay.as_ref()
+    {
+        recording.write(path);
+    }
+}
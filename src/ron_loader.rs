@@ -0,0 +1,47 @@
+// Asset loader for anything that deserializes from a RON file
+
+use bevy::asset::io::Reader;
+use bevy::asset::{Asset, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::io;
+use std::marker::PhantomData;
+
+pub struct RonLoader<A> {
+    /// Double extensions like `stats.ron`, so each asset type gets its own loader
+    extensions: &'static [&'static str],
+    asset: PhantomData<fn() -> A>,
+}
+
+impl<A> RonLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        RonLoader {
+            extensions,
+            asset: PhantomData,
+        }
+    }
+}
+
+impl<A: Asset + for<'de> Deserialize<'de>> AssetLoader for RonLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = io::Error;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<A, io::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            ron::de::from_bytes(&bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
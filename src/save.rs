@@ -0,0 +1,348 @@
+use crate::combat::{Ammo, Health, Score};
+use crate::level::{EntityState, RespawnPoint, VisitedLevels, WorldState};
+use crate::player::{Items, Player};
+use crate::replay::InputReplay;
+use crate::speedrun::PersonalBest;
+use crate::storage;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Bump when the layout of `SaveGame` changes, and handle the old version in `migrate`
+pub const SAVE_VERSION: u32 = 2;
+pub const SAVE_SLOTS: usize = 3;
+
+/// Save slots, loading them back, and the play time they store
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveSlot>()
+            .init_resource::<Playtime>()
+            .add_event::<SaveRequest>()
+            .add_event::<LoadRequest>()
+            .add_systems(
+                Update,
+                (tick_playtime, save_load_input, write_save, read_save),
+            );
+    }
+}
+
+/// Which save slot F5/F9 and autosaves use, picked with F1-F3
+#[derive(Resource, Default)]
+pub struct SaveSlot(pub usize);
+
+#[derive(Resource, Default)]
+pub struct Playtime(pub Duration);
+
+#[derive(Event)]
+pub struct SaveRequest;
+
+#[derive(Event)]
+pub struct LoadRequest;
+
+// Every field falls back to its default, so saves missing newer fields still load,
+// and unknown fields are ignored, so saves from before settings moved out still load
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveGame {
+    /// Saves written before versioning was added read as version 0
+    pub version: u32,
+    pub level_iid: Option<String>,
+    /// Where the player stood in `level_iid`
+    pub player_position: Option<[f32; 3]>,
+    pub checkpoint: Option<SavedCheckpoint>,
+    pub items: Vec<String>,
+    pub health: Option<u32>,
+    pub ammo: u32,
+    pub coins: u32,
+    pub points: u32,
+    pub world_state: HashMap<String, EntityState>,
+    pub playtime_secs: f32,
+    pub visited_levels: Vec<String>,
+    pub personal_best: PersonalBest,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SavedCheckpoint {
+    pub level_iid: String,
+    pub position: [f32; 3],
+}
+
+fn slot_key(slot: usize) -> String {
+    format!("slot_{}", slot + 1)
+}
+
+/// Upgrades saves written by older versions of the game
+fn migrate(mut save: SaveGame) -> SaveGame {
+    // Version 0 has the same layout as version 1, it just predates the field
+    if save.version == 0 {
+        save.version = 1;
+    }
+    // Version 1 has no player position, which loading falls back from
+    if save.version == 1 {
+        save.version = 2;
+    }
+    save
+}
+
+pub fn load_slot(slot: usize) -> Option<SaveGame> {
+    let text = storage::read(&slot_key(slot))?;
+    match ron::from_str::<SaveGame>(&text) {
+        Ok(save) if save.version > SAVE_VERSION => {
+            warn!("Save slot {} is from a newer version of the game", slot + 1);
+            None
+        }
+        Ok(save) => Some(migrate(save)),
+        Err(err) => {
+            warn!("Save slot {} is corrupted: {err}", slot + 1);
+            None
+        }
+    }
+}
+
+fn write_slot(slot: usize, save: &SaveGame) {
+    let text = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())
+        .expect("save game should always serialize");
+    match storage::write(&slot_key(slot), &text) {
+        Ok(()) => info!("Saved to slot {}", slot + 1),
+        Err(err) => warn!("Could not save to slot {}: {err}", slot + 1),
+    }
+}
+
+/// Updates only the personal best in the save in `slot`.
+/// An empty slot gets it with its first save instead.
+pub fn store_personal_best(slot: usize, personal_best: &PersonalBest) {
+    let Some(mut save) = load_slot(slot) else {
+        return;
+    };
+    save.personal_best = personal_best.clone();
+    write_slot(slot, &save);
+}
+
+fn tick_playtime(time: Res<Time>, mut playtime: ResMut<Playtime>) {
+    playtime.0 += time.delta();
+}
+
+fn save_load_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut slot: ResMut<SaveSlot>,
+    mut save_requests: EventWriter<SaveRequest>,
+    mut load_requests: EventWriter<LoadRequest>,
+) {
+    for (index, key) in [KeyCode::F1, KeyCode::F2, KeyCode::F3]
+        .into_iter()
+        .enumerate()
+        .take(SAVE_SLOTS)
+    {
+        if input.just_pressed(key) {
+            slot.0 = index;
+            info!("Using save slot {}", index + 1);
+        }
+    }
+    if input.just_pressed(KeyCode::F5) {
+        save_requests.send(SaveRequest);
+    }
+    if input.just_pressed(KeyCode::F9) {
+        load_requests.send(LoadRequest);
+    }
+}
+
+fn write_save(
+    mut save_requests: EventReader<SaveRequest>,
+    slot: Res<SaveSlot>,
+    player: Query<(&Items, &Health, &Ammo, &Transform), With<Player>>,
+    level_selection: Res<LevelSelection>,
+    respawn_point: Res<RespawnPoint>,
+    world_state: Res<WorldState>,
+    score: Res<Score>,
+    playtime: Res<Playtime>,
+    visited_levels: Res<VisitedLevels>,
+    personal_best: Res<PersonalBest>,
+) {
+    // Several requests in one frame still only need one write
+    if save_requests.read().count() == 0 {
+        return;
+    }
+    let Ok((items, health, ammo, transform)) = player.get_single() else {
+        return;
+    };
+
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        level_iid: match level_selection.as_ref() {
+            LevelSelection::Iid(level_iid) => Some(level_iid.to_string()),
+            _ => None,
+        },
+        player_position: Some(transform.translation.to_array()),
+        checkpoint: respawn_point
+            .level_iid
+            .clone()
+            .map(|level_iid| SavedCheckpoint {
+                level_iid,
+                position: respawn_point.position.to_array(),
+            }),
+        items: items.0.clone(),
+        health: Some(health.current),
+        ammo: ammo.0,
+        coins: score.coins,
+        points: score.points,
+        world_state: world_state.0.clone(),
+        playtime_secs: playtime.0.as_secs_f32(),
+        visited_levels: visited_levels.0.iter().cloned().collect(),
+        personal_best: personal_best.clone(),
+    };
+    write_slot(slot.0, &save);
+}
+
+fn read_save(
+    mut cmd: Commands,
+    mut load_requests: EventReader<LoadRequest>,
+    slot: Res<SaveSlot>,
+    mut player: Query<
+        (
+            &mut Items,
+            &mut Health,
+            &mut Ammo,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        With<Player>,
+    >,
+    levels: Query<Entity, With<LevelIid>>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut respawn_point: ResMut<RespawnPoint>,
+    mut world_state: ResMut<WorldState>,
+    mut score: ResMut<Score>,
+    mut playtime: ResMut<Playtime>,
+    mut visited_levels: ResMut<VisitedLevels>,
+    mut personal_best: ResMut<PersonalBest>,
+    replay: Res<InputReplay>,
+) {
+    if load_requests.read().count() == 0 {
+        return;
+    }
+    // Recordings always start from a new game, a loaded save would play back differently
+    if replay.is_active() {
+        warn!("Loading is off while recording or replaying input");
+        return;
+    }
+    let Some(save) = load_slot(slot.0) else {
+        info!("Nothing to load in slot {}", slot.0 + 1);
+        return;
+    };
+    let Ok((mut items, mut health, mut ammo, mut transform, mut velocity)) =
+        player.get_single_mut()
+    else {
+        return;
+    };
+
+    *respawn_point = save
+        .checkpoint
+        .map_or_else(RespawnPoint::default, |checkpoint| RespawnPoint {
+            level_iid: Some(checkpoint.level_iid),
+            position: Vec3::from_array(checkpoint.position),
+        });
+    // Older saves only know the level, not where in it the player was,
+    // so they start over from the checkpoint
+    let (level, position) = match (save.level_iid, save.player_position) {
+        (Some(level_iid), Some(position)) => {
+            (LevelSelection::iid(level_iid), Vec3::from_array(position))
+        }
+        _ => (
+            respawn_point
+                .level_iid
+                .clone()
+                .map_or(LevelSelection::index(0), LevelSelection::iid),
+            respawn_point.position,
+        ),
+    };
+    *level_selection = level;
+    transform.translation = position;
+    velocity.linvel = Vec2::ZERO;
+
+    items.0 = save.items;
+    health.current = save.health.unwrap_or(health.max).min(health.max);
+    ammo.0 = save.ammo;
+    score.coins = save.coins;
+    score.points = save.points;
+    playtime.0 = Duration::try_from_secs_f32(save.playtime_secs).unwrap_or_default();
+    *world_state = WorldState(save.world_state);
+    *visited_levels = VisitedLevels(save.visited_levels.into_iter().collect());
+    *personal_best = save.personal_best;
+
+    // Respawn the loaded levels so the restored world state applies to them too.
+    // The worldly player is kept, so its restored state survives.
+    for level in &levels {
+        cmd.entity(level).insert(Respawn);
+    }
+    info!("Loaded slot {}", slot.0 + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written before saves had a version, while settings were still saved with the game
+    const VERSION_0_SAVE: &str = r#"(
+        level_iid: Some("b67a4e80-d7b0-11ee-8232-5f7aef2d0874"),
+        checkpoint: Some((
+            level_iid: "dddc1800-d7b0-11ee-8232-c51a3d363312",
+            position: (136.0, 184.0, 0.0),
+        )),
+        items: ["RedKey"],
+        health: Some(2),
+        coins: 3,
+        points: 130,
+        world_state: {
+            "105ead30-d7b0-11ee-bb58-53c3c73a0196": Killed,
+        },
+        playtime_secs: 42.5,
+        settings: (vsync: true, screen_shake: false),
+    )"#;
+
+    #[test]
+    // Migrating moves the parsed play time over untouched, so it has to come out bit for bit
+    #[allow(clippy::float_cmp)]
+    fn migrates_version_0_save() {
+        let save = migrate(ron::from_str(VERSION_0_SAVE).expect("version 0 save should parse"));
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(
+            save.level_iid.as_deref(),
+            Some("b67a4e80-d7b0-11ee-8232-5f7aef2d0874")
+        );
+        assert_eq!(save.player_position, None);
+        let checkpoint = save.checkpoint.expect("checkpoint should be kept");
+        assert_eq!(checkpoint.level_iid, "dddc1800-d7b0-11ee-8232-c51a3d363312");
+        assert_eq!(checkpoint.position, [136.0, 184.0, 0.0]);
+        assert_eq!(save.items, ["RedKey"]);
+        assert_eq!(save.health, Some(2));
+        assert_eq!((save.coins, save.points), (3, 130));
+        assert_eq!(
+            save.world_state.get("105ead30-d7b0-11ee-bb58-53c3c73a0196"),
+            Some(&EntityState::Killed)
+        );
+        assert_eq!(save.playtime_secs, 42.5);
+        assert!(save.visited_levels.is_empty());
+    }
+
+    #[test]
+    fn current_save_round_trips() {
+        let save = SaveGame {
+            version: SAVE_VERSION,
+            level_iid: Some("d63aa260-d7b0-11ee-a794-a775e1eaf8cb".to_string()),
+            player_position: Some([211.0, 204.0, 0.0]),
+            ..Default::default()
+        };
+        let text = ron::to_string(&save).expect("save should serialize");
+        let loaded = migrate(ron::from_str(&text).expect("save should parse"));
+
+        assert_eq!(loaded.version, SAVE_VERSION);
+        assert_eq!(loaded.level_iid, save.level_iid);
+        assert_eq!(loaded.player_position, save.player_position);
+    }
+}
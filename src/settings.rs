@@ -0,0 +1,415 @@
+use crate::components::{Action, Language, Settings, WindowModeSetting};
+use crate::pause::{PauseReason, PauseReasons};
+use crate::storage;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode};
+
+/// Window sizes offered in the menu, all 16:9 like `BASE_RES`
+const RESOLUTIONS: [Vec2; 4] = [
+    Vec2::new(960.0, 540.0),
+    Vec2::new(1280.0, 720.0),
+    Vec2::new(1600.0, 900.0),
+    Vec2::new(1920.0, 1080.0),
+];
+
+const VOLUME_STEP: f32 = 0.1;
+
+/// The settings menu, and applying and storing the player's settings
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SettingsMenu>()
+            .add_systems(Startup, setup_settings_menu)
+            .add_systems(
+                PreUpdate,
+                (settings_menu_input, block_game_input)
+                    .chain()
+                    .after(InputSystem)
+                    .after(crate::replay::ReplayInput),
+            )
+            .add_systems(Update, (update_settings_menu, apply_settings).chain());
+    }
+}
+
+/// Reads the stored settings, falling back to the defaults
+pub fn load() -> Settings {
+    let Some(text) = storage::read("settings") else {
+        return Settings::default();
+    };
+    ron::from_str(&text).unwrap_or_else(|err| {
+        warn!("Settings are corrupted, using the defaults: {err}");
+        Settings::default()
+    })
+}
+
+fn store(settings: &Settings) {
+    let text = ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default())
+        .expect("settings should always serialize");
+    if let Err(err) = storage::write("settings", &text) {
+        warn!("Could not store settings: {err}");
+    }
+}
+
+pub fn present_mode(settings: &Settings) -> PresentMode {
+    if settings.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    }
+}
+
+pub fn window_mode(settings: &Settings) -> WindowMode {
+    match settings.window_mode {
+        WindowModeSetting::Windowed => WindowMode::Windowed,
+        WindowModeSetting::BorderlessFullscreen => WindowMode::BorderlessFullscreen,
+        WindowModeSetting::Fullscreen => WindowMode::Fullscreen,
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    selected: usize,
+    /// Waiting for a key to bind to the selected action
+    rebinding: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum MenuItem {
+    Vsync,
+    WindowMode,
+    Resolution,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    ScreenShake,
+    ShowTimer,
+    SpeedrunTimer,
+    Language,
+    Bind(Action),
+    Back,
+}
+
+const MENU_ITEMS: [MenuItem; 18] = [
+    MenuItem::Vsync,
+    MenuItem::WindowMode,
+    MenuItem::Resolution,
+    MenuItem::MasterVolume,
+    MenuItem::MusicVolume,
+    MenuItem::SfxVolume,
+    MenuItem::ScreenShake,
+    MenuItem::ShowTimer,
+    MenuItem::SpeedrunTimer,
+    MenuItem::Language,
+    MenuItem::Bind(Action::Left),
+    MenuItem::Bind(Action::Right),
+    MenuItem::Bind(Action::Up),
+    MenuItem::Bind(Action::Down),
+    MenuItem::Bind(Action::Jump),
+    MenuItem::Bind(Action::Slash),
+    MenuItem::Bind(Action::Interact),
+    MenuItem::Back,
+];
+
+impl MenuItem {
+    fn label(self) -> &'static str {
+        match self {
+            MenuItem::Vsync => "VSYNC",
+            MenuItem::WindowMode => "WINDOW MODE",
+            MenuItem::Resolution => "RESOLUTION",
+            MenuItem::MasterVolume => "MASTER VOLUME",
+            MenuItem::MusicVolume => "MUSIC VOLUME",
+            MenuItem::SfxVolume => "SFX VOLUME",
+            MenuItem::ScreenShake => "SCREEN SHAKE",
+            MenuItem::ShowTimer => "SHOW TIMER",
+            MenuItem::SpeedrunTimer => "SPEEDRUN TIMER",
+            MenuItem::Language => "LANGUAGE",
+            MenuItem::Bind(Action::Left) => "LEFT",
+            MenuItem::Bind(Action::Right) => "RIGHT",
+            MenuItem::Bind(Action::Up) => "UP",
+            MenuItem::Bind(Action::Down) => "DOWN",
+            MenuItem::Bind(Action::Jump) => "JUMP",
+            MenuItem::Bind(Action::Slash) => "SLASH",
+            MenuItem::Bind(Action::Interact) => "INTERACT",
+            MenuItem::Back => "BACK",
+        }
+    }
+
+    fn value(self, settings: &Settings) -> String {
+        let language = settings.language;
+        let on_off = |on: bool| language.tr(if on { "ON" } else { "OFF" }).to_string();
+        let percent = |volume: f32| format!("{:.0}%", volume * 100.);
+        match self {
+            MenuItem::Vsync => on_off(settings.vsync),
+            MenuItem::WindowMode => language
+                .tr(match settings.window_mode {
+                    WindowModeSetting::Windowed => "WINDOWED",
+                    WindowModeSetting::BorderlessFullscreen => "BORDERLESS",
+                    WindowModeSetting::Fullscreen => "FULLSCREEN",
+                })
+                .to_string(),
+            MenuItem::Resolution => {
+                format!("{}x{}", settings.resolution.x, settings.resolution.y)
+            }
+            MenuItem::MasterVolume => percent(settings.master_volume),
+            MenuItem::MusicVolume => percent(settings.music_volume),
+            MenuItem::SfxVolume => percent(settings.sfx_volume),
+            MenuItem::ScreenShake => on_off(settings.screen_shake),
+            MenuItem::ShowTimer => on_off(settings.show_timer),
+            MenuItem::SpeedrunTimer => on_off(settings.speedrun_timer),
+            MenuItem::Language => language
+                .tr(match language {
+                    Language::English => "ENGLISH",
+                    Language::French => "FRENCH",
+                })
+                .to_string(),
+            MenuItem::Bind(binding) => {
+                // "KeyA" reads better as just "A"
+                let key = format!("{:?}", settings.key_bindings.key(binding));
+                key.strip_prefix("Key").unwrap_or(&key).to_uppercase()
+            }
+            MenuItem::Back => String::new(),
+        }
+    }
+
+    /// Steps the setting forwards or backwards
+    fn adjust(self, settings: &mut Settings, forwards: bool) {
+        let step = |index: usize, len: usize| {
+            if forwards {
+                (index + 1) % len
+            } else {
+                (index + len - 1) % len
+            }
+        };
+        let volume = |volume: &mut f32| {
+            let delta = if forwards { VOLUME_STEP } else { -VOLUME_STEP };
+            // Round so repeated steps don't drift away from whole percentages
+            *volume = ((*volume + delta) / VOLUME_STEP).round().clamp(0., 10.) * VOLUME_STEP;
+        };
+        match self {
+            MenuItem::Vsync => settings.vsync = !settings.vsync,
+            MenuItem::WindowMode => {
+                let modes = [
+                    WindowModeSetting::Windowed,
+                    WindowModeSetting::BorderlessFullscreen,
+                    WindowModeSetting::Fullscreen,
+                ];
+                let index = modes
+                    .iter()
+                    .position(|mode| *mode == settings.window_mode)
+                    .unwrap_or_default();
+                settings.window_mode = modes[step(index, modes.len())];
+            }
+            MenuItem::Resolution => {
+                let index = RESOLUTIONS
+                    .iter()
+                    .position(|resolution| *resolution == settings.resolution)
+                    .unwrap_or(1);
+                settings.resolution = RESOLUTIONS[step(index, RESOLUTIONS.len())];
+            }
+            MenuItem::MasterVolume => volume(&mut settings.master_volume),
+            MenuItem::MusicVolume => volume(&mut settings.music_volume),
+            MenuItem::SfxVolume => volume(&mut settings.sfx_volume),
+            MenuItem::ScreenShake => settings.screen_shake = !settings.screen_shake,
+            MenuItem::ShowTimer => settings.show_timer = !settings.show_timer,
+            MenuItem::SpeedrunTimer => settings.speedrun_timer = !settings.speedrun_timer,
+            MenuItem::Language => {
+                let index = Language::ALL
+                    .iter()
+                    .position(|language| *language == settings.language)
+                    .unwrap_or_default();
+                settings.language = Language::ALL[step(index, Language::ALL.len())];
+            }
+            MenuItem::Bind(_) | MenuItem::Back => {}
+        }
+    }
+}
+
+/// Marker for the menu's full screen backdrop
+#[derive(Component)]
+struct SettingsMenuRoot;
+
+#[derive(Component)]
+struct SettingsMenuText;
+
+fn setup_settings_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("Orbitron-Bold.ttf");
+    commands
+        .spawn((
+            SettingsMenuRoot,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.7)),
+                // above the HUD, below the FPS counter
+                z_index: ZIndex::Global(i32::MAX - 1),
+                visibility: Visibility::Hidden,
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                SettingsMenuText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ));
+        });
+}
+
+/// Escape opens and closes the menu, arrows pick and change options, Enter toggles and rebinds
+pub fn settings_menu_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    mut pause_reasons: ResMut<PauseReasons>,
+) {
+    if menu.rebinding {
+        if let Some(key) = input.get_just_pressed().next() {
+            // Escape backs out instead of being bound
+            if *key != KeyCode::Escape {
+                let MenuItem::Bind(binding) = MENU_ITEMS[menu.selected] else {
+                    unreachable!("only key binding rows start rebinding");
+                };
+                *settings.key_bindings.key_mut(binding) = *key;
+            }
+            menu.rebinding = false;
+        }
+        return;
+    }
+
+    let close = menu.open
+        && (input.just_pressed(KeyCode::Escape)
+            || (input.just_pressed(KeyCode::Enter) && MENU_ITEMS[menu.selected] == MenuItem::Back));
+    if close {
+        menu.open = false;
+        pause_reasons.remove(PauseReason::SettingsMenu);
+        store(&settings);
+        return;
+    }
+    if !menu.open {
+        if input.just_pressed(KeyCode::Escape) {
+            menu.open = true;
+            menu.selected = 0;
+            pause_reasons.insert(PauseReason::SettingsMenu);
+        }
+        return;
+    }
+
+    let rows = MENU_ITEMS.len();
+    if input.just_pressed(KeyCode::ArrowDown) {
+        menu.selected = (menu.selected + 1) % rows;
+    }
+    if input.just_pressed(KeyCode::ArrowUp) {
+        menu.selected = (menu.selected + rows - 1) % rows;
+    }
+    let item = MENU_ITEMS[menu.selected];
+    if input.just_pressed(KeyCode::ArrowRight) {
+        item.adjust(&mut settings, true);
+    }
+    if input.just_pressed(KeyCode::ArrowLeft) {
+        item.adjust(&mut settings, false);
+    }
+    if input.just_pressed(KeyCode::Enter) {
+        if let MenuItem::Bind(_) = item {
+            menu.rebinding = true;
+        } else {
+            item.adjust(&mut settings, true);
+        }
+    }
+}
+
+/// Keeps key presses meant for the menu away from the game
+fn block_game_input(menu: Res<SettingsMenu>, mut input: ResMut<ButtonInput<KeyCode>>) {
+    if menu.open {
+        input.reset_all();
+    }
+}
+
+fn update_settings_menu(
+    menu: Res<SettingsMenu>,
+    settings: Res<Settings>,
+    mut roots: Query<&mut Visibility, With<SettingsMenuRoot>>,
+    mut texts: Query<&mut Text, With<SettingsMenuText>>,
+) {
+    if !menu.is_changed() && !settings.is_changed() {
+        return;
+    }
+    for mut visibility in &mut roots {
+        *visibility = if menu.open {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    let language = settings.language;
+    for mut text in &mut texts {
+        let style = text.sections[0].style.clone();
+        let mut sections = vec![TextSection::new(
+            format!("{}\n\n", language.tr("SETTINGS")),
+            style.clone(),
+        )];
+        for (index, item) in MENU_ITEMS.iter().enumerate() {
+            let selected = index == menu.selected;
+            let value = if selected && menu.rebinding {
+                language.tr("PRESS A KEY").to_string()
+            } else {
+                item.value(&settings)
+            };
+            let line = if value.is_empty() {
+                format!("{}\n", language.tr(item.label()))
+            } else {
+                format!("{}  < {value} >\n", language.tr(item.label()))
+            };
+            sections.push(TextSection::new(
+                line,
+                TextStyle {
+                    color: if selected { Color::GOLD } else { Color::WHITE },
+                    ..style.clone()
+                },
+            ));
+        }
+        text.sections = sections;
+    }
+}
+
+/// Applies window settings as soon as they change
+fn apply_settings(settings: Res<Settings>, mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    let present_mode = present_mode(&settings);
+    if window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+    let mode = window_mode(&settings);
+    if window.mode != mode {
+        window.mode = mode;
+    }
+    // The web build's size follows its canvas instead
+    if !cfg!(target_family = "wasm")
+        && mode == WindowMode::Windowed
+        && Vec2::new(window.resolution.width(), window.resolution.height()) != settings.resolution
+    {
+        window
+            .resolution
+            .set(settings.resolution.x, settings.resolution.y);
+    }
+}
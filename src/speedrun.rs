@@ -0,0 +1,276 @@
+use crate::components::Settings;
+use crate::map::selected_level_iid;
+use crate::player::Player;
+use crate::save::{self, LoadRequest, SaveSlot};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Splits listed on screen, newest last
+const SHOWN_SPLITS: usize = 5;
+
+/// Speedrun timer with per-level splits and a personal best
+pub struct SpeedrunPlugin;
+
+impl Plugin for SpeedrunPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpeedrunTimer>()
+            .insert_resource(
+                save::load_slot(0)
+                    .map(|save| save.personal_best)
+                    .unwrap_or_default(),
+            )
+            .add_systems(Startup, setup_speedrun_text)
+            .add_systems(
+                Update,
+                (
+                    start_run,
+                    invalidate_run_on_load,
+                    tick_speedrun_timer,
+                    record_splits,
+                    speedrun_text_update_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Split {
+    pub level_iid: String,
+    pub name: String,
+    pub igt: Duration,
+    /// Seconds ahead (negative) or behind (positive) the personal best
+    pub delta: Option<f32>,
+    /// Fastest this level has ever been reached from the one before
+    pub gold: bool,
+}
+
+/// A run starts with a new game and splits on the first entry into each level
+#[derive(Resource, Default)]
+pub struct SpeedrunTimer {
+    pub running: bool,
+    /// Real time, including pauses
+    pub rta: Duration,
+    /// Game time, stopped while the game is paused
+    pub igt: Duration,
+    pub splits: Vec<Split>,
+    entered: HashSet<String>,
+}
+
+/// Stored alongside the save, the one in the first slot applies from launch
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersonalBest {
+    /// In-game seconds at each split of the best complete run, keyed by level iid
+    pub splits: HashMap<String, f32>,
+    pub final_time: Option<f32>,
+    /// Fastest in-game seconds to reach each level from the previous split, over all runs
+    pub best_segments: HashMap<String, f32>,
+}
+
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}.{:03}", secs / 60, secs % 60, time.subsec_millis())
+}
+
+/// The player spawning is the start of a new game
+fn start_run(player: Query<(), Added<Player>>, mut timer: ResMut<SpeedrunTimer>) {
+    if !player.is_empty() {
+        *timer = SpeedrunTimer {
+            running: true,
+            ..Default::default()
+        };
+    }
+}
+
+/// Loading a save mid-run would make the times meaningless
+fn invalidate_run_on_load(
+    mut load_requests: EventReader<LoadRequest>,
+    mut timer: ResMut<SpeedrunTimer>,
+) {
+    if load_requests.read().count() > 0 && timer.running {
+        info!("Speedrun stopped by loading a save");
+        timer.running = false;
+    }
+}
+
+fn tick_speedrun_timer(
+    real_time: Res<Time<Real>>,
+    time: Res<Time>,
+    mut timer: ResMut<SpeedrunTimer>,
+) {
+    if timer.running {
+        timer.rta += real_time.delta();
+        timer.igt += time.delta();
+    }
+}
+
+fn record_splits(
+    level_selection: Res<LevelSelection>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut timer: ResMut<SpeedrunTimer>,
+    mut personal_best: ResMut<PersonalBest>,
+    slot: Res<SaveSlot>,
+) {
+    if !timer.running {
+        return;
+    }
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    let Some(level_iid) = selected_level_iid(&level_selection, ldtk_project) else {
+        return;
+    };
+    if !timer.entered.insert(level_iid.clone()) {
+        return;
+    }
+    // The starting level is where the run begins, not a split
+    if timer.entered.len() == 1 {
+        return;
+    }
+
+    let igt = timer.igt.as_secs_f32();
+    let segment = igt
+        - timer
+            .splits
+            .last()
+            .map_or(0., |split| split.igt.as_secs_f32());
+    let gold = personal_best
+        .best_segments
+        .get(&level_iid)
+        .map_or(true, |best| segment < *best);
+    if gold {
+        personal_best
+            .best_segments
+            .insert(level_iid.clone(), segment);
+    }
+    let split = Split {
+        name: ldtk_project
+            .get_raw_level_by_iid(&level_iid)
+            .map(|level| level.identifier.replace('_', " ").to_uppercase())
+            .unwrap_or_default(),
+        delta: personal_best.splits.get(&level_iid).map(|pb| igt - pb),
+        level_iid,
+        igt: timer.igt,
+        gold,
+    };
+    timer.splits.push(split);
+
+    // Every level reached finishes the run
+    let mut improved = gold;
+    if timer.entered.len() == ldtk_project.iter_raw_levels().count() {
+        timer.running = false;
+        if personal_best.final_time.map_or(true, |pb| igt < pb) {
+            improved = true;
+            info!("New personal best: {}", format_time(timer.igt));
+            personal_best.final_time = Some(igt);
+            personal_best.splits = timer
+                .splits
+                .iter()
+                .map(|split| (split.level_iid.clone(), split.igt.as_secs_f32()))
+                .collect();
+        }
+    }
+    if improved {
+        save::store_personal_best(slot.0, &personal_best);
+    }
+}
+
+/// Marker to find the text entity so we can update it
+#[derive(Component)]
+struct SpeedrunText;
+
+fn setup_speedrun_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            visibility: Visibility::Hidden,
+            style: Style {
+                position_type: PositionType::Absolute,
+                // top-right corner, under the FPS counter
+                right: Val::Percent(1.),
+                top: Val::Percent(8.),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                SpeedrunText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("Orbitron-Bold.ttf"),
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ));
+        });
+}
+
+fn speedrun_text_update_system(
+    settings: Res<Settings>,
+    timer: Res<SpeedrunTimer>,
+    mut texts: Query<(&mut Text, &Parent), With<SpeedrunText>>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    for (mut text, parent) in &mut texts {
+        if let Ok(mut visibility) = visibilities.get_mut(parent.get()) {
+            let wanted = if settings.speedrun_timer {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+            if *visibility != wanted {
+                *visibility = wanted;
+            }
+        }
+        if !settings.speedrun_timer {
+            continue;
+        }
+
+        let style = text.sections[0].style.clone();
+        let mut sections = vec![TextSection::new(
+            format!(
+                "RTA {}\nIGT {}",
+                format_time(timer.rta),
+                format_time(timer.igt)
+            ),
+            style.clone(),
+        )];
+        for split in timer.splits.iter().rev().take(SHOWN_SPLITS).rev() {
+            sections.push(TextSection::new(
+                format!("\n{}  {}", split.name, format_time(split.igt)),
+                style.clone(),
+            ));
+            // Gold beats the best segment, otherwise green is ahead of the PB and red behind
+            if let Some(delta) = split.delta {
+                let color = if split.gold {
+                    Color::GOLD
+                } else if delta < 0. {
+                    Color::GREEN
+                } else {
+                    Color::RED
+                };
+                sections.push(TextSection::new(
+                    format!("  {delta:+.2}"),
+                    TextStyle {
+                        color,
+                        ..style.clone()
+                    },
+                ));
+            }
+        }
+        text.sections = sections;
+    }
+}
@@ -0,0 +1,245 @@
+// Movement stats for each kind of character, read from `assets/characters.stats.ron`.
+// The file is watched on native builds, saving it re-applies the stats to everything alive.
+// Single entities can override them with fields of their own in LDTK.
+
+use crate::enemy::PatrolSpeedStat;
+use crate::player::{AccelerationStat, FakeGroundFrictionStat, JumpForceStat, MaxSpeedStat};
+use crate::ron_loader::RonLoader;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Loads the stats table and keeps characters in sync with it
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<StatsTable>()
+            .register_asset_loader(RonLoader::<StatsTable>::new(&["stats.ron"]))
+            .add_systems(Startup, load_stats_table)
+            .add_systems(Update, apply_character_stats);
+    }
+}
+
+/// Stats of one kind of character, anything left out doesn't apply to it
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CharacterStats {
+    /// Horizontal acceleration while a direction is held, in pixels per second squared
+    pub acceleration: Option<f32>,
+    /// Fastest speed on each axis, in pixels per second
+    pub max_speed: Option<Vec2>,
+    pub jump_force: Option<f32>,
+    /// How quickly the horizontal speed dies down on the ground, per second
+    pub ground_friction: Option<f32>,
+    pub patrol: Option<PatrolSpeedStat>,
+}
+
+/// Stats set on a single LDTK entity, on top of its row in the table
+#[derive(Clone, Debug, Default, Component)]
+pub struct StatOverrides {
+    pub acceleration: Option<f32>,
+    pub max_speed_x: Option<f32>,
+    pub max_speed_y: Option<f32>,
+    pub jump_force: Option<f32>,
+    pub ground_friction: Option<f32>,
+    pub patrol_speed: Option<f32>,
+    pub patrol_turn_speed: Option<f32>,
+}
+
+impl StatOverrides {
+    /// Stats made of two values only apply once both are known, from the override or the table
+    fn apply_to(&self, stats: &CharacterStats) -> CharacterStats {
+        let max_speed_x = self
+            .max_speed_x
+            .or(stats.max_speed.map(|max_speed| max_speed.x));
+        let max_speed_y = self
+            .max_speed_y
+            .or(stats.max_speed.map(|max_speed| max_speed.y));
+        let patrol_speed = self
+            .patrol_speed
+            .or(stats.patrol.map(|patrol| patrol.speed));
+        let patrol_turn_speed = self
+            .patrol_turn_speed
+            .or(stats.patrol.map(|patrol| patrol.turn_speed));
+        CharacterStats {
+            acceleration: self.acceleration.or(stats.acceleration),
+            max_speed: max_speed_x.zip(max_speed_y).map(|(x, y)| Vec2::new(x, y)),
+            jump_force: self.jump_force.or(stats.jump_force),
+            ground_friction: self.ground_friction.or(stats.ground_friction),
+            patrol: patrol_speed
+                .zip(patrol_turn_speed)
+                .map(|(speed, turn_speed)| PatrolSpeedStat { speed, turn_speed }),
+        }
+    }
+}
+
+/// Stats keyed by the LDTK identifier of the character
+#[derive(Asset, TypePath, Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct StatsTable(pub HashMap<String, CharacterStats>);
+
+#[derive(Resource)]
+struct StatsTableHandle(Handle<StatsTable>);
+
+/// Which row of the stats table a character uses
+#[derive(Clone, PartialEq, Eq, Debug, Default, Component)]
+pub struct CharacterKind(pub String);
+
+impl From<&EntityInstance> for CharacterKind {
+    fn from(entity_instance: &EntityInstance) -> CharacterKind {
+        CharacterKind(entity_instance.identifier.clone())
+    }
+}
+
+fn load_stats_table(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(StatsTableHandle(asset_server.load("characters.stats.ron")));
+}
+
+/// Gives new characters their stats, and everyone new stats whenever the table (re)loads
+fn apply_character_stats(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<StatsTable>>,
+    handle: Res<StatsTableHandle>,
+    tables: Res<Assets<StatsTable>>,
+    characters: Query<(Entity, Ref<CharacterKind>, Option<&StatOverrides>)>,
+) {
+    let reloaded = events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(&handle.0) || event.is_modified(&handle.0));
+    let Some(table) = tables.get(&handle.0) else {
+        return;
+    };
+    for (entity, kind, overrides) in &characters {
+        if !reloaded && !kind.is_added() {
+            continue;
+        }
+        let stats = match (table.0.get(&kind.0), overrides) {
+            (Some(stats), None) => stats.clone(),
+            (Some(stats), Some(overrides)) => overrides.apply_to(stats),
+            (None, Some(overrides)) => {
+                warn!("No stats for {}, only its own fields apply", kind.0);
+                overrides.apply_to(&CharacterStats::default())
+            }
+            (None, None) => {
+                warn!("No stats for {}, it won't be able to move", kind.0);
+                continue;
+            }
+        };
+        let mut entity_commands = commands.entity(entity);
+        if let Some(acceleration) = stats.acceleration {
+            entity_commands.insert(AccelerationStat(acceleration));
+        }
+        if let Some(max_speed) = stats.max_speed {
+            entity_commands.insert(MaxSpeedStat(max_speed));
+        }
+        if let Some(jump_force) = stats.jump_force {
+            entity_commands.insert(JumpForceStat(jump_force));
+        }
+        if let Some(ground_friction) = stats.ground_friction {
+            entity_commands.insert(FakeGroundFrictionStat(ground_friction));
+        }
+        if let Some(patrol) = stats.patrol {
+            entity_commands.insert(patrol);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snake_stats() -> CharacterStats {
+        CharacterStats {
+            acceleration: Some(400.),
+            max_speed: Some(Vec2::new(100., 300.)),
+            jump_force: None,
+            ground_friction: Some(10.),
+            patrol: Some(PatrolSpeedStat {
+                speed: 30.,
+                turn_speed: 15.,
+            }),
+        }
+    }
+
+    #[test]
+    fn no_overrides_keep_the_table_row() {
+        let stats = StatOverrides::default().apply_to(&snake_stats());
+        assert_eq!(stats.acceleration, Some(400.));
+        assert_eq!(stats.max_speed, Some(Vec2::new(100., 300.)));
+        assert_eq!(stats.jump_force, None);
+        assert_eq!(stats.ground_friction, Some(10.));
+        assert_eq!(stats.patrol, snake_stats().patrol);
+    }
+
+    #[test]
+    fn overrides_replace_single_stats() {
+        let overrides = StatOverrides {
+            acceleration: Some(800.),
+            jump_force: Some(250.),
+            ..Default::default()
+        };
+        let stats = overrides.apply_to(&snake_stats());
+        assert_eq!(stats.acceleration, Some(800.));
+        assert_eq!(stats.jump_force, Some(250.));
+        assert_eq!(stats.ground_friction, Some(10.));
+    }
+
+    #[test]
+    fn one_axis_of_max_speed_keeps_the_other_from_the_table() {
+        let overrides = StatOverrides {
+            max_speed_x: Some(50.),
+            ..Default::default()
+        };
+        let stats = overrides.apply_to(&snake_stats());
+        assert_eq!(stats.max_speed, Some(Vec2::new(50., 300.)));
+
+        let overrides = StatOverrides {
+            max_speed_y: Some(120.),
+            ..Default::default()
+        };
+        let stats = overrides.apply_to(&snake_stats());
+        assert_eq!(stats.max_speed, Some(Vec2::new(100., 120.)));
+    }
+
+    #[test]
+    fn one_patrol_speed_keeps_the_other_from_the_table() {
+        let overrides = StatOverrides {
+            patrol_turn_speed: Some(60.),
+            ..Default::default()
+        };
+        let stats = overrides.apply_to(&snake_stats());
+        assert_eq!(
+            stats.patrol,
+            Some(PatrolSpeedStat {
+                speed: 30.,
+                turn_speed: 60.,
+            })
+        );
+    }
+
+    #[test]
+    fn overrides_without_a_table_row_leave_half_set_stats_unset() {
+        let overrides = StatOverrides {
+            max_speed_x: Some(80.),
+            patrol_speed: Some(40.),
+            ..Default::default()
+        };
+        let stats = overrides.apply_to(&CharacterStats::default());
+        assert_eq!(stats.acceleration, None);
+        assert_eq!(stats.max_speed, None);
+        assert_eq!(stats.patrol, None);
+    }
+
+    #[test]
+    fn overrides_without_a_table_row_apply_when_complete() {
+        let overrides = StatOverrides {
+            max_speed_x: Some(80.),
+            max_speed_y: Some(200.),
+            ..Default::default()
+        };
+        let stats = overrides.apply_to(&CharacterStats::default());
+        assert_eq!(stats.max_speed, Some(Vec2::new(80., 200.)));
+    }
+}
@@ -0,0 +1,46 @@
+// Persistent key/value storage:
+// files in a `saves` folder on native, browser `localStorage` on the web build
+
+use std::io;
+
+#[cfg(not(target_family = "wasm"))]
+fn path(key: &str) -> std::path::PathBuf {
+    std::path::Path::new("saves").join(format!("{key}.ron"))
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn read(key: &str) -> Option<String> {
+    std::fs::read_to_string(path(key)).ok()
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn write(key: &str, value: &str) -> io::Result<()> {
+    let path = path(key);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, value)
+}
+
+#[cfg(target_family = "wasm")]
+fn local_storage() -> io::Result<web_sys::Storage> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "localStorage is unavailable"))
+}
+
+#[cfg(target_family = "wasm")]
+pub fn read(key: &str) -> Option<String> {
+    local_storage()
+        .ok()?
+        .get_item(&format!("bouncy_king_{key}"))
+        .ok()
+        .flatten()
+}
+
+#[cfg(target_family = "wasm")]
+pub fn write(key: &str, value: &str) -> io::Result<()> {
+    local_storage()?
+        .set_item(&format!("bouncy_king_{key}"), value)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{err:?}")))
+}